@@ -1,37 +1,439 @@
-use std::collections::HashSet;
+use crate::config::GlobalConfig;
+use crate::error::{MultiCheckError, Result};
+use crate::report::CheckStatus;
+use crate::runner::{cargo_bin, check_env};
 use std::fs::File;
-use std::io;
-use std::io::Write;
-use std::io::{BufRead, BufReader};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{env, fs};
+use tempfile::NamedTempFile;
 
-pub fn read_cache(cache_file: &str) -> io::Result<(u64, HashSet<Vec<String>>)> {
+/// First line of every cache file, bumped whenever the format changes so that
+/// caches written by other versions are regenerated rather than misread.
+const CACHE_HEADER: &str = "MULTICHECK-CACHE v2";
+
+/// Status of combinations that have no known result.
+const PENDING: &str = "pending";
+
+/// Returns the default cache location in cargo's `target_dir`,
+/// `multi-check/combinations.cache`, where git usually ignores it.
+pub fn default_cache_path(target_dir: &Path) -> PathBuf {
+    target_dir.join("multi-check").join("combinations.cache")
+}
+
+/// Returns where the cache of the project at `project_path` used to be kept
+/// by default, see [`migrate_cache`].
+///
+/// Caches lived in `$XDG_CACHE_HOME/multi-feature-check` (falling back to
+/// `~/.cache` and then the system temp directory), named after a hash of the
+/// canonicalized project path so that different projects never share a file.
+pub fn legacy_cache_path(project_path: &Path) -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+
+    let mut hasher = DefaultHasher::new();
+    project_path.hash(&mut hasher);
+
+    base.join("multi-feature-check")
+        .join(format!("{:016x}.cache", hasher.finish()))
+}
+
+/// Moves the cache at `legacy`, and the failures saved next to it, to
+/// `cache_file` unless it already exists, so that the results of the runs
+/// made before the default location changed are kept. Returns whether the
+/// cache was moved.
+pub fn migrate_cache(legacy: &Path, cache_file: &Path) -> Result<bool> {
+    if cache_file.exists() || !legacy.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = cache_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    for (from, to) in [
+        (legacy.to_path_buf(), cache_file.to_path_buf()),
+        (failures_path(legacy), failures_path(cache_file)),
+    ] {
+        if from.exists() {
+            // Copied rather than renamed, the cache directory may be on
+            // another file system.
+            fs::copy(&from, &to)?;
+            fs::remove_file(&from)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the file next to `cache_file` listing the combinations that failed
+/// in the last run, as read by `--rerun-failed`.
+pub fn failures_path(cache_file: &Path) -> PathBuf {
+    cache_file.with_extension("failed")
+}
+
+/// First lines of a cache file, the combinations follow as [`CacheEntry`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CacheHeader {
+    /// Hash of the features and settings the combinations were generated from.
+    pub hash: u64,
+    /// Hash of the sources and cargo settings the results were obtained with,
+    /// see [`sources_hash`].
+    pub sources_hash: u64,
+}
+
+/// A cached combination with its last result, `None` if it wasn't checked.
+pub type CacheEntry = (Vec<String>, Option<CheckStatus>);
+
+/// Hashes what the result of a check depends on besides the features: the
+/// cargo settings, `Cargo.lock` and the modification time of every file of
+/// the project, `target` and hidden directories aside. `cache_file`, its
+/// failures and the `log_dir` change with every run, they are left out too.
+pub fn sources_hash(project_dir: &Path, configs: &GlobalConfig, cache_file: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    cargo_bin(configs).hash(&mut hasher);
+    for command in configs.commands() {
+        command.as_str().hash(&mut hasher);
+        check_env(configs, command).hash(&mut hasher);
+    }
+    configs.target.hash(&mut hasher);
+    configs.profile.hash(&mut hasher);
+    configs.keep_default_features.hash(&mut hasher);
+    configs.toolchain.hash(&mut hasher);
+    configs.manifest_path.hash(&mut hasher);
+    configs.package.hash(&mut hasher);
+    configs.extra_cargo_args.hash(&mut hasher);
+    configs.feature_cargo_args.hash(&mut hasher);
+    if let Ok(lock) = fs::read(project_dir.join("Cargo.lock")) {
+        lock.hash(&mut hasher);
+    }
+
+    let mut excluded = vec![resolved(cache_file), resolved(&failures_path(cache_file))];
+    // `log_dir` is the directory of this run, within the one given with `--log-dir`.
+    excluded.extend(
+        configs
+            .log_dir
+            .as_deref()
+            .and_then(Path::parent)
+            .map(resolved),
+    );
+    let mut files = Vec::new();
+    collect_mtimes(project_dir, project_dir, &excluded, &mut files);
+    files.sort();
+    files.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns `path` made absolute with symbolic links resolved, as far as it
+/// exists, to compare it with the paths found under the canonical project
+/// directory.
+fn resolved(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    match (path.parent().map(Path::canonicalize), path.file_name()) {
+        (Some(Ok(dir)), Some(name)) => dir.join(name),
+        _ => path,
+    }
+}
+
+fn collect_mtimes(
+    root: &Path,
+    dir: &Path,
+    excluded: &[PathBuf],
+    files: &mut Vec<(PathBuf, SystemTime)>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.')
+            || (dir == root && name == "target")
+            || excluded.contains(&path)
+        {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_mtimes(root, &path, excluded, files);
+        } else if let Ok(modified) = metadata.modified() {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            files.push((relative, modified));
+        }
+    }
+}
+
+fn invalid_data(message: String) -> MultiCheckError {
+    MultiCheckError::CacheCorrupt(message)
+}
+
+/// Reads a cache written by [`write_cache`]: the version header and the two
+/// hashes on their own lines, then one combination per line preceded by its
+/// last result. Caches of another version are rejected as invalid data.
+///
+/// Only the header is read upfront, the entries are read lazily from the
+/// returned iterator so that large caches are never held in memory.
+pub fn read_cache(
+    cache_file: &Path,
+) -> Result<(CacheHeader, impl Iterator<Item = Result<CacheEntry>>)> {
     let file = File::open(cache_file)?;
     let reader = BufReader::new(file);
     let mut lines = reader.lines();
 
-    let hash = lines.next().unwrap()?.parse::<u64>().unwrap();
-    let mut combinations: HashSet<Vec<String>> = HashSet::new();
+    match lines.next().transpose()? {
+        Some(header) if header.trim() == CACHE_HEADER => {}
+        Some(header) => {
+            return Err(invalid_data(format!(
+                "Unsupported cache version {:?}",
+                header
+            )))
+        }
+        None => return Err(invalid_data("Cache file is empty".to_string())),
+    }
+
+    let mut read_hash = |name: &str| match lines.next() {
+        Some(line) => line?
+            .trim()
+            .parse::<u64>()
+            .map_err(|err| invalid_data(format!("Invalid cache {}: {}", name, err))),
+        None => Err(invalid_data(format!("Cache file has no {}", name))),
+    };
+    let header = CacheHeader {
+        hash: read_hash("hash")?,
+        sources_hash: read_hash("sources hash")?,
+    };
 
-    for line in lines {
+    let entries = lines.map(|line| {
         let line = line?;
-        let combo: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
-        combinations.insert(combo);
+        let mut words = line.split_whitespace();
+        let status = match words.next() {
+            Some(PENDING) => None,
+            Some("passed") => Some(CheckStatus::Passed),
+            Some("failed") => Some(CheckStatus::Failed),
+            _ => return Err(invalid_data(format!("Invalid cache line: {:?}", line))),
+        };
+        Ok((words.map(|s| s.to_string()).collect(), status))
+    });
+    Ok((header, entries))
+}
+
+/// Writes `header` and `entries` to `cache_file`, returning the number of
+/// entries written.
+///
+/// The cache is written to a temporary file of the same directory first and
+/// then renamed, so that `entries` can be read from the cache being replaced
+/// and an interrupted write leaves the previous cache whole. The temporary
+/// file is removed when writing fails.
+pub fn write_cache(
+    cache_file: &Path,
+    header: &CacheHeader,
+    entries: impl IntoIterator<Item = Result<CacheEntry>>,
+) -> Result<u64> {
+    let dir = cache_file
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let temp_file = NamedTempFile::new_in(dir)?;
+    let mut file = BufWriter::new(temp_file.as_file());
+
+    writeln!(file, "{}", CACHE_HEADER)?;
+    writeln!(file, "{}", header.hash)?;
+    writeln!(file, "{}", header.sources_hash)?;
+    let mut count = 0;
+    for entry in entries {
+        let (combo, status) = entry?;
+        let status = match status {
+            Some(CheckStatus::Passed) => "passed",
+            Some(CheckStatus::Failed | CheckStatus::Ignored | CheckStatus::Invalid) => "failed",
+            Some(CheckStatus::Skipped) | None => PENDING,
+        };
+        writeln!(file, "{} {}", status, combo.join(" "))?;
+        count += 1;
     }
+    file.flush()?;
+    drop(file);
+    // Renamed only once the contents are on disk, a crash could otherwise
+    // leave the new name pointing at an empty file.
+    temp_file.as_file().sync_all()?;
 
-    Ok((hash, combinations))
+    temp_file
+        .persist(cache_file)
+        .map_err(|err| MultiCheckError::Io(err.error))?;
+    Ok(count)
 }
 
-pub fn write_cache(
-    cache_file: &str,
-    hash: u64,
-    combinations: &HashSet<Vec<String>>,
-) -> io::Result<()> {
-    let mut file = File::create(cache_file)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::strings;
+
+    #[test]
+    fn read_cache_rejects_empty_file() {
+        let file = NamedTempFile::new().unwrap();
+
+        let err = read_cache(file.path()).err().unwrap();
+        assert!(matches!(err, MultiCheckError::CacheCorrupt(_)));
+    }
+
+    #[test]
+    fn read_cache_rejects_garbage_hash() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "{}", CACHE_HEADER).unwrap();
+        writeln!(file, "not a hash").unwrap();
+        writeln!(file, "feature1 feature2").unwrap();
+
+        let err = read_cache(file.path()).err().unwrap();
+        assert!(matches!(err, MultiCheckError::CacheCorrupt(_)));
+    }
+
+    #[test]
+    fn read_cache_rejects_v1_cache() {
+        // v1 had no header: the hash, then one combination per line.
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "12345").unwrap();
+        writeln!(file, "feature1 feature2").unwrap();
+
+        let err = read_cache(file.path()).err().unwrap();
+        assert!(matches!(err, MultiCheckError::CacheCorrupt(_)));
+        assert!(err.to_string().contains("Unsupported cache version"));
+    }
+
+    #[test]
+    fn read_cache_rejects_unknown_status() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "{}", CACHE_HEADER).unwrap();
+        writeln!(file, "1").unwrap();
+        writeln!(file, "2").unwrap();
+        writeln!(file, "feature1 feature2").unwrap();
+
+        let (_, mut entries) = read_cache(file.path()).unwrap();
+        let err = entries.next().unwrap().unwrap_err();
+        assert!(matches!(err, MultiCheckError::CacheCorrupt(_)));
+    }
+
+    #[test]
+    fn read_cache_round_trips_write_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("project.cache");
+        let header = CacheHeader {
+            hash: 42,
+            sources_hash: 7,
+        };
+        let entries = vec![
+            (strings(&["feature1"]), Some(CheckStatus::Passed)),
+            (
+                strings(&["feature1", "feature2"]),
+                Some(CheckStatus::Failed),
+            ),
+            (vec![], None),
+        ];
+
+        let written = write_cache(&path, &header, entries.iter().cloned().map(Ok)).unwrap();
+
+        assert_eq!(written, 3);
+        let (read_header, read_entries) = read_cache(&path).unwrap();
+        assert_eq!(read_header, header);
+        assert_eq!(read_entries.collect::<Result<Vec<_>>>().unwrap(), entries);
+    }
+
+    #[test]
+    fn write_cache_can_stream_from_the_cache_it_replaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("project.cache");
+        let header = CacheHeader::default();
+        let entries = (0..3).map(|i| Ok((vec![format!("f{}", i)], None)));
+        write_cache(&path, &header, entries).unwrap();
+
+        let (_, old_entries) = read_cache(&path).unwrap();
+        let updated =
+            old_entries.map(|entry| entry.map(|(combo, _)| (combo, Some(CheckStatus::Passed))));
+        write_cache(&path, &header, updated).unwrap();
+
+        let (_, entries) = read_cache(&path).unwrap();
+        let statuses: Vec<_> = entries.map(|entry| entry.unwrap().1).collect();
+        assert_eq!(statuses, vec![Some(CheckStatus::Passed); 3]);
+    }
+
+    #[test]
+    fn failed_write_keeps_the_previous_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("project.cache");
+        let header = CacheHeader::default();
+        write_cache(&path, &header, [Ok((strings(&["a"]), None))]).unwrap();
+
+        let entries = [
+            Ok((strings(&["b"]), None)),
+            Err(MultiCheckError::CacheCorrupt("interrupted".to_string())),
+        ];
+        assert!(write_cache(&path, &header, entries).is_err());
+
+        let (_, entries) = read_cache(&path).unwrap();
+        let combos: Vec<_> = entries.map(|entry| entry.unwrap().0).collect();
+        assert_eq!(combos, vec![strings(&["a"])]);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 
-    writeln!(file, "{}", hash)?;
-    for combo in combinations {
-        writeln!(file, "{}", combo.join(" "))?;
+    #[test]
+    fn legacy_cache_is_moved_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("cache/0123.cache");
+        let cache_file = default_cache_path(&dir.path().join("project/target"));
+        fs::create_dir(dir.path().join("cache")).unwrap();
+        fs::write(&legacy, "old").unwrap();
+        fs::write(failures_path(&legacy), "a").unwrap();
+
+        assert!(migrate_cache(&legacy, &cache_file).unwrap());
+        assert_eq!(fs::read_to_string(&cache_file).unwrap(), "old");
+        assert_eq!(fs::read_to_string(failures_path(&cache_file)).unwrap(), "a");
+        assert!(!legacy.exists());
+
+        fs::write(&legacy, "stale").unwrap();
+        assert!(!migrate_cache(&legacy, &cache_file).unwrap());
+        assert_eq!(fs::read_to_string(&cache_file).unwrap(), "old");
     }
 
-    Ok(())
+    #[test]
+    fn sources_hash_tracks_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let configs = crate::test_support::global_config();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        let cache_file = dir.path().join("target/combinations.cache");
+        let before = sources_hash(dir.path(), &configs, &cache_file);
+
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join("target/out"), "").unwrap();
+        assert_eq!(sources_hash(dir.path(), &configs, &cache_file), before);
+
+        fs::write(dir.path().join("src/new.rs"), "").unwrap();
+        assert_ne!(sources_hash(dir.path(), &configs, &cache_file), before);
+    }
+
+    #[test]
+    fn sources_hash_ignores_the_files_written_by_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        // Like the project directory, which is canonical.
+        let root = dir.path().canonicalize().unwrap();
+        let mut configs = crate::test_support::global_config();
+        configs.log_dir = Some(root.join("logs/2"));
+        fs::write(root.join("lib.rs"), "").unwrap();
+        let cache_file = root.join("multi-check.cache");
+        let before = sources_hash(&root, &configs, &cache_file);
+
+        fs::write(&cache_file, "cache").unwrap();
+        fs::write(failures_path(&cache_file), "a b").unwrap();
+        for run in ["logs/1", "logs/2"] {
+            fs::create_dir_all(root.join(run)).unwrap();
+            fs::write(root.join(run).join("a.log"), "").unwrap();
+        }
+
+        assert_eq!(sources_hash(&root, &configs, &cache_file), before);
+    }
 }
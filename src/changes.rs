@@ -0,0 +1,234 @@
+use crate::error::{MultiCheckError, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Files whose changes may affect every feature.
+const MANIFEST_FILES: [&str; 3] = ["Cargo.toml", "Cargo.lock", "build.rs"];
+
+/// Extensions of the documentation files, whose changes affect no feature.
+const DOC_EXTENSIONS: [&str; 1] = ["md"];
+
+/// Returns the files of `project_dir` changed since `git_ref`, committed or
+/// not, relative to `project_dir`. Untracked files aren't listed.
+pub fn changed_files(project_dir: &Path, git_ref: &str) -> Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args(["diff", "--name-only", "--relative", git_ref, "--"])
+        .current_dir(project_dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(MultiCheckError::Git {
+            git_ref: git_ref.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Returns the features affected by changes to `files`, sorted, or `None`
+/// when every feature is.
+///
+/// With `paths`, a file affects the features whose paths contain it, and any
+/// other file but documentation affects every feature, since sources read
+/// data files with `include_bytes!` or from `build.rs`. Without, Rust
+/// sources affect the features named in their `feature = "..."` predicates,
+/// as in `#[cfg(feature = "...")]`, and every feature without any, like
+/// other files but documentation. Changes to `Cargo.toml`, `Cargo.lock` or
+/// `build.rs` always affect every feature.
+pub fn affected_features(
+    project_dir: &Path,
+    files: &[PathBuf],
+    paths: &HashMap<String, Vec<PathBuf>>,
+) -> Option<Vec<String>> {
+    let mut affected = BTreeSet::new();
+    for file in files {
+        if file
+            .file_name()
+            .is_some_and(|name| MANIFEST_FILES.iter().any(|manifest| name == *manifest))
+        {
+            return None;
+        }
+        let is_source = file.extension().is_some_and(|ext| ext == "rs");
+        if !paths.is_empty() {
+            let mapped: Vec<&String> = paths
+                .iter()
+                .filter(|(_, paths)| paths.iter().any(|path| file.starts_with(path)))
+                .map(|(feature, _)| feature)
+                .collect();
+            if mapped.is_empty() && !is_documentation(file) {
+                return None;
+            }
+            affected.extend(mapped.into_iter().cloned());
+        } else if is_source {
+            // Deleted files can't be read, the `mod` item removed along with
+            // them tells what they affected.
+            if let Ok(source) = fs::read_to_string(project_dir.join(file)) {
+                let features = cfg_features(&source);
+                // Code gated by no feature is built by every combination.
+                if features.is_empty() {
+                    return None;
+                }
+                affected.extend(features);
+            }
+        } else if !is_documentation(file) {
+            return None;
+        }
+    }
+    Some(affected.into_iter().collect())
+}
+
+/// Returns whether `file` is documentation, see [`DOC_EXTENSIONS`].
+fn is_documentation(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|ext| DOC_EXTENSIONS.iter().any(|doc| ext == *doc))
+}
+
+/// Returns the entries of `paths` that don't exist in `project_dir`, as
+/// `(feature, path)` pairs sorted by feature.
+pub fn stale_paths<'a>(
+    project_dir: &Path,
+    paths: &'a HashMap<String, Vec<PathBuf>>,
+) -> Vec<(&'a String, &'a PathBuf)> {
+    let mut stale: Vec<_> = paths
+        .iter()
+        .flat_map(|(feature, paths)| paths.iter().map(move |path| (feature, path)))
+        .filter(|(_, path)| !project_dir.join(path).exists())
+        .collect();
+    stale.sort();
+    stale
+}
+
+/// Returns the features named in the `feature = "..."` predicates of `source`.
+fn cfg_features(source: &str) -> Vec<String> {
+    let mut features = Vec::new();
+    let mut rest = source;
+    while let Some(pos) = rest.find("feature") {
+        rest = &rest[pos + "feature".len()..];
+        let name = rest
+            .trim_start()
+            .strip_prefix('=')
+            .map(str::trim_start)
+            .and_then(|value| value.strip_prefix('"'))
+            .and_then(|value| value.split_once('"'))
+            .map(|(name, _)| name);
+        if let Some(name) = name {
+            features.push(name.to_string());
+        }
+    }
+    features
+}
+
+/// Extends `changed` with the features enabling one of them, directly or
+/// transitively, through `dependencies` or `requires`: combinations with
+/// those build the changed code too. The result is sorted.
+pub fn features_enabling(
+    changed: &[String],
+    dependencies: &HashMap<String, Vec<String>>,
+    requires: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut affected: BTreeSet<String> = changed.iter().cloned().collect();
+    loop {
+        let enabling: Vec<String> = dependencies
+            .iter()
+            .chain(requires)
+            .filter(|(feature, enabled)| {
+                !affected.contains(*feature) && enabled.iter().any(|f| affected.contains(f))
+            })
+            .map(|(feature, _)| feature.clone())
+            .collect();
+        if enabling.is_empty() {
+            return affected.into_iter().collect();
+        }
+        affected.extend(enabling);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::strings;
+
+    #[test]
+    fn cfg_predicates_name_features() {
+        let source = "#[cfg(feature = \"a\")]\nfn a() {}\n\
+                      #[cfg(all(feature=\"b\", not(feature = \"c\")))]\n\
+                      fn b() { if cfg!(feature = \"d\") {} }\n\
+                      // feature flags\n";
+
+        assert_eq!(cfg_features(source), strings(&["a", "b", "c", "d"]));
+    }
+
+    #[test]
+    fn affected_features_come_from_sources_and_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[cfg(feature = \"a\")]\nmod a;\n#[cfg(feature = \"untested\")]\nmod u;\n",
+        )
+        .unwrap();
+        let affected = |files: &[&str]| {
+            let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+            affected_features(dir.path(), &files, &HashMap::new())
+        };
+
+        assert_eq!(affected(&["src/lib.rs"]), Some(strings(&["a", "untested"])));
+        assert_eq!(affected(&["src/removed.rs", "README.md"]), Some(Vec::new()));
+        assert_eq!(affected(&["README.md", "Cargo.toml"]), None);
+
+        fs::write(dir.path().join("src/util.rs"), "pub fn shared() {}\n").unwrap();
+        assert_eq!(affected(&["src/lib.rs", "src/util.rs"]), None);
+        assert_eq!(affected(&["src/lib.rs", "src/data.json"]), None);
+    }
+
+    #[test]
+    fn declared_paths_map_files_to_features() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = HashMap::from([
+            (
+                "tls".to_string(),
+                vec![PathBuf::from("src/tls.rs"), PathBuf::from("src/tls/")],
+            ),
+            ("json".to_string(), vec![PathBuf::from("src/json.rs")]),
+        ]);
+        let affected = |files: &[&str]| {
+            let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+            affected_features(dir.path(), &files, &paths)
+        };
+
+        assert_eq!(
+            affected(&["src/tls/conn.rs", "src/json.rs", "README.md"]),
+            Some(strings(&["json", "tls"]))
+        );
+        assert_eq!(affected(&["src/lib.rs", "src/tls.rs"]), None);
+        assert_eq!(affected(&["src/tls.rs", "src/data/table.json"]), None);
+        assert_eq!(affected(&["src/tls/certs.pem"]), Some(strings(&["tls"])));
+
+        fs::create_dir_all(dir.path().join("src/tls")).unwrap();
+        fs::write(dir.path().join("src/json.rs"), "").unwrap();
+        assert_eq!(
+            stale_paths(dir.path(), &paths),
+            vec![(&"tls".to_string(), &PathBuf::from("src/tls.rs"))]
+        );
+    }
+
+    #[test]
+    fn features_enabling_changed_ones_are_affected() {
+        let dependencies = HashMap::from([
+            ("a".to_string(), strings(&["b"])),
+            ("b".to_string(), strings(&["c"])),
+            ("d".to_string(), Vec::new()),
+        ]);
+        let requires = HashMap::from([("e".to_string(), strings(&["a"]))]);
+
+        assert_eq!(
+            features_enabling(&strings(&["c"]), &dependencies, &requires),
+            strings(&["a", "b", "c", "e"])
+        );
+    }
+}
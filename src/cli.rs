@@ -0,0 +1,247 @@
+use clap::Parser;
+use multi_feature_check::config::Strategy;
+use multi_feature_check::progress::ColorChoice;
+use multi_feature_check::report::OutputFormat;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Configuration file used when none is given.
+const DEFAULT_CONFIG: &str = "Configs.toml";
+
+/// Checks a cargo project with many combinations of its features.
+#[derive(Parser, Debug)]
+#[command(name = "cargo-multi-check", bin_name = "cargo multi-check", version)]
+pub struct Cli {
+    /// Directory of the project to check, the directory of the manifest or the current one by default.
+    pub project: Option<String>,
+    /// Configuration file listing the features to test, `Configs.toml` by default, `-` for stdin.
+    /// A file name pattern such as `configs/*.toml` merges the matching files.
+    pub config: Option<String>,
+    /// Manifest to read the features from, `<PROJECT>/Cargo.toml` by default.
+    pub cargo_toml: Option<String>,
+    /// Path to the Cargo.toml of the project, same as the CARGO_TOML argument.
+    #[arg(long, value_name = "PATH", conflicts_with = "cargo_toml")]
+    pub manifest_path: Option<String>,
+    /// Configuration file, same as the CONFIG argument. Repeat it to merge
+    /// several, later ones overriding earlier ones.
+    #[arg(long = "config", value_name = "PATH", conflicts_with = "config")]
+    pub config_file: Vec<String>,
+    /// How strict features are combined: full, pairwise or isolated, overrides the configuration.
+    #[arg(long, value_name = "STRATEGY", value_parser = parse_strategy)]
+    pub strategy: Option<Strategy>,
+    /// Where to cache the generated combinations, `multi-check/combinations.cache` in cargo's target directory by default.
+    #[arg(long, value_name = "PATH")]
+    pub cache_file: Option<PathBuf>,
+    /// Check every combination again, even those that passed last time with unchanged sources.
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Format of the final summary: human, json, tap or sarif.
+    #[arg(long, value_name = "FORMAT", default_value = "human", value_parser = parse_output_format)]
+    pub output: OutputFormat,
+    /// Write the JSON report to this file.
+    #[arg(long, value_name = "PATH")]
+    pub report_file: Option<PathBuf>,
+    /// Write a JUnit XML report to this file.
+    #[arg(long, value_name = "PATH")]
+    pub junit_file: Option<PathBuf>,
+    /// Print the combinations that would be checked and exit.
+    #[arg(long, visible_alias = "list")]
+    pub dry_run: bool,
+    /// Write the combinations that would be checked to PATH and exit, as JSON
+    /// for `.json` files and one combination per line otherwise.
+    #[arg(long, value_name = "PATH")]
+    pub export_combos: Option<PathBuf>,
+    /// Check the combinations of a file written by `--export-combos` instead
+    /// of generating them, without using the cache.
+    #[arg(long, value_name = "PATH", conflicts_with = "export_combos")]
+    pub import_combos: Option<PathBuf>,
+    /// Only check combinations containing a feature affected by the changes
+    /// since this git ref.
+    #[arg(long, value_name = "REF", conflicts_with = "import_combos")]
+    pub since: Option<String>,
+    /// Only check the combinations that failed in the last run, without generating any.
+    #[arg(long, conflicts_with_all = ["import_combos", "export_combos", "since", "count"])]
+    pub rerun_failed: bool,
+    /// Print the number of combinations that would be checked and exit.
+    #[arg(long, conflicts_with_all = ["dry_run", "export_combos", "watch", "init"])]
+    pub count: bool,
+    /// Explain why a feature or combination, its features separated by spaces
+    /// or commas, is or isn't generated, and exit.
+    #[arg(long, value_name = "FEATURES", conflicts_with_all = ["dry_run", "count", "export_combos", "watch", "init"])]
+    pub explain: Option<String>,
+    /// Write the output of every check to a new directory in PATH, one log file
+    /// per combination.
+    #[arg(long, value_name = "PATH")]
+    pub log_dir: Option<PathBuf>,
+    /// Level of the diagnostic logs printed to stderr, overrides `RUST_LOG`: error, warn, info, debug or trace.
+    #[arg(long, value_name = "LEVEL", value_parser = ["error", "warn", "info", "debug", "trace"])]
+    pub log_level: Option<String>,
+    /// Only print the failing combinations, nothing when every check passes.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Workspace member to check.
+    #[arg(short, long, value_name = "NAME")]
+    pub package: Option<String>,
+    /// Target triple to check for.
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+    /// Cargo profile to check with, e.g. release, overrides the configuration.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Rustup toolchain to check with.
+    #[arg(long, value_name = "NAME")]
+    pub toolchain: Option<String>,
+    /// Color the progress: auto, always or never. Auto honors NO_COLOR and CARGO_TERM_COLOR.
+    #[arg(long, value_name = "WHEN", default_value = "auto", value_parser = parse_color)]
+    pub color: ColorChoice,
+    /// Print plain progress lines instead of progress bars, the default on CI or without a terminal.
+    #[arg(long)]
+    pub no_progress: bool,
+    /// Stream cargo's output instead of capturing it, checks run one at a time.
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// Exit with 0 even when some combinations fail, for advisory runs.
+    #[arg(long)]
+    pub exit_zero: bool,
+    /// Shrink each failing combination to a minimal set of features that still fails.
+    #[arg(long)]
+    pub minimize: bool,
+    /// Maximum number of extra checks run by --minimize.
+    #[arg(long, value_name = "N")]
+    pub minimize_budget: Option<usize>,
+    /// Only check combinations containing this feature, can be repeated.
+    #[arg(long = "only-feature", value_name = "NAME")]
+    pub only_features: Vec<String>,
+    /// Skip combinations containing this feature, can be repeated.
+    #[arg(long = "skip-feature", value_name = "NAME")]
+    pub skip_features: Vec<String>,
+    /// Run even when more combinations than `max_combinations` are planned.
+    #[arg(short, long)]
+    pub yes: bool,
+    /// Check again whenever the sources or the manifest change, until Ctrl-C.
+    #[arg(long, conflicts_with_all = ["dry_run", "init"])]
+    pub watch: bool,
+    /// Write a starter configuration listing the features of the project to CONFIG.
+    #[arg(long)]
+    pub init: bool,
+    /// Overwrite an existing configuration with --init.
+    #[arg(long, requires = "init")]
+    pub force: bool,
+}
+
+impl Cli {
+    /// Parses the command line, also when run as `cargo multi-check` where
+    /// cargo passes `multi-check` as the first argument.
+    pub fn parse_args() -> Self {
+        Self::parse_from(strip_subcommand(env::args_os().collect()))
+    }
+
+    /// Returns the manifest given with `--manifest-path` or as an argument.
+    pub fn manifest(&self) -> Option<&String> {
+        self.manifest_path.as_ref().or(self.cargo_toml.as_ref())
+    }
+
+    pub fn project_dir(&self) -> String {
+        if let Some(project) = &self.project {
+            return project.clone();
+        }
+        match self.manifest().map(Path::new).and_then(Path::parent) {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.display().to_string(),
+            _ => ".".to_string(),
+        }
+    }
+
+    /// Returns the configuration files to merge, in order.
+    pub fn config_paths(&self) -> Vec<&str> {
+        if !self.config_file.is_empty() {
+            return self.config_file.iter().map(String::as_str).collect();
+        }
+        vec![self.config.as_deref().unwrap_or(DEFAULT_CONFIG)]
+    }
+}
+
+fn strip_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    if args.get(1).is_some_and(|arg| arg == "multi-check") {
+        args.remove(1);
+    }
+    args
+}
+
+fn parse_strategy(value: &str) -> Result<Strategy, String> {
+    Strategy::parse(value).ok_or_else(|| format!("unknown strategy `{}`", value))
+}
+
+fn parse_color(value: &str) -> Result<ColorChoice, String> {
+    ColorChoice::parse(value).ok_or_else(|| format!("unknown color choice `{}`", value))
+}
+
+fn parse_output_format(value: &str) -> Result<OutputFormat, String> {
+    OutputFormat::parse(value).ok_or_else(|| format!("unknown output format `{}`", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Cli {
+        let args = args.iter().map(OsString::from).collect();
+        Cli::try_parse_from(strip_subcommand(args)).unwrap()
+    }
+
+    #[test]
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn cargo_subcommand_token_is_stripped() {
+        let direct = parse(&["cargo-multi-check", ".", "Configs.toml"]);
+        let via_cargo = parse(&["cargo-multi-check", "multi-check", ".", "Configs.toml"]);
+
+        assert_eq!(direct.project_dir(), ".");
+        assert_eq!(via_cargo.project_dir(), ".");
+        assert_eq!(via_cargo.config_paths(), vec!["Configs.toml"]);
+        assert_eq!(via_cargo.manifest(), None);
+    }
+
+    #[test]
+    fn named_options_replace_positional_paths() {
+        let cli = parse(&[
+            "cargo-multi-check",
+            "--manifest-path",
+            "crates/demo/Cargo.toml",
+            "--config",
+            "ci.toml",
+            "--strategy",
+            "pairwise",
+        ]);
+
+        assert_eq!(cli.project_dir(), "crates/demo");
+        assert_eq!(cli.config_paths(), vec!["ci.toml"]);
+        assert_eq!(cli.manifest().unwrap(), "crates/demo/Cargo.toml");
+        assert_eq!(cli.strategy, Some(Strategy::Pairwise));
+    }
+
+    #[test]
+    fn paths_default_to_the_current_directory() {
+        let cli = parse(&["cargo-multi-check"]);
+
+        assert_eq!(cli.project_dir(), ".");
+        assert_eq!(cli.config_paths(), vec!["Configs.toml"]);
+    }
+
+    #[test]
+    fn repeated_config_options_are_kept_in_order() {
+        let cli = parse(&[
+            "cargo-multi-check",
+            "--config",
+            "base.toml",
+            "--config",
+            "crate.toml",
+        ]);
+
+        assert_eq!(cli.config_paths(), vec!["base.toml", "crate.toml"]);
+    }
+}
@@ -0,0 +1,1009 @@
+use crate::config::{GlobalConfig, Strategy};
+use crate::pairwise::pairwise_selections;
+use crate::project::RustProject;
+use std::collections::{BTreeSet, HashSet};
+use std::iter;
+use tracing::{debug, trace};
+
+/// Lazily generates the combinations of `project`, each exactly once and
+/// smallest first for the full strategy, so that memory use doesn't grow with
+/// the number of combinations.
+///
+/// The order only depends on the features and settings, never on hashing, so
+/// that caches and the order checks are dispatched in are the same every run.
+pub fn generate_combinations(project: &RustProject) -> impl Iterator<Item = Vec<String>> + '_ {
+    let n = project.features.len();
+    let max_size = project.configs.max_combination_size;
+    let fits = move |len: usize| max_size.is_none_or(|max| len <= max);
+    let implied: Vec<HashSet<&String>> = project
+        .features
+        .iter()
+        .map(|feature| implied_features(project, feature))
+        .collect();
+    let implied_by_selection = implied.clone();
+    let implied_by_extras: Vec<HashSet<&String>> = project
+        .extra_features
+        .iter()
+        .map(|feature| implied_features(project, feature))
+        .collect();
+    // Combining the features the defaults already enable builds nothing new.
+    let defaults = enabled_by_default(project);
+    let on_by_default: Vec<bool> = project
+        .features
+        .iter()
+        .map(|feature| defaults.contains(feature))
+        .collect();
+    let on_by_default_in_row = on_by_default.clone();
+    let defaults_of_extras = defaults.clone();
+
+    let selections: Box<dyn Iterator<Item = Vec<usize>> + '_> = match project.configs.strategy {
+        // A subset of features that implies one of its own members builds the
+        // same as the smaller subset without it, which is generated as well.
+        Strategy::Full => Box::new(Subsets::new(n, max_size.unwrap_or(n)).filter(
+            move |selection| {
+                !selection.iter().any(|&i| on_by_default[i])
+                    && exclude_dependencies(project, &implied, selection).len() == selection.len()
+            },
+        )),
+        Strategy::Pairwise => {
+            // Rows that only differ by implied features reduce to the same combination.
+            let mut seen = HashSet::new();
+            Box::new(
+                pairwise_selections(n)
+                    .into_iter()
+                    .map(move |row| {
+                        // Dropping the conflicting features keeps the other pairs of the row covered.
+                        let selection: Vec<usize> = (0..n)
+                            .filter(|&i| row[i] && !on_by_default_in_row[i])
+                            .fold(Vec::new(), |mut kept, i| {
+                                let feature = &project.features[i];
+                                if !kept.iter().any(|&j: &usize| {
+                                    exclusive(project, feature, &project.features[j])
+                                }) {
+                                    kept.push(i);
+                                }
+                                kept
+                            });
+                        exclude_dependencies(project, &implied, &selection)
+                    })
+                    .filter(move |selection| {
+                        !selection.is_empty()
+                            && fits(selection.len())
+                            && seen.insert(selection.clone())
+                    }),
+            )
+        }
+        Strategy::Isolated => Box::new(iter::empty()),
+    };
+
+    let extras_only = project
+        .extra_features
+        .iter()
+        .filter(move |extra| fits(1) && !defaults.contains(extra))
+        .map(|extra| vec![extra.clone()]);
+    let strict = selections.flat_map(move |selection| {
+        let combo: Vec<String> = selection
+            .iter()
+            .map(|&i| project.features[i].clone())
+            .collect();
+        let extras = if fits(combo.len() + 1) {
+            project.extra_features.as_slice()
+        } else {
+            &[]
+        };
+        // An extra feature that the combination already enables adds nothing,
+        // one enabling a feature of the combination gives the same build as
+        // a smaller combination with it.
+        let enabled: HashSet<&String> = selection
+            .iter()
+            .flat_map(|&i| implied_by_selection[i].iter().copied())
+            .collect();
+        let extras: Vec<&String> = extras
+            .iter()
+            .zip(&implied_by_extras)
+            .filter(|(extra, implied)| {
+                !enabled.contains(extra)
+                    && !defaults_of_extras.contains(*extra)
+                    && !combo.iter().any(|feature| implied.contains(feature))
+            })
+            .map(|(extra, _)| extra)
+            .collect();
+        let extended = extras.into_iter().map({
+            let combo = combo.clone();
+            move |extra| {
+                let mut extended_combo = combo.clone();
+                extended_combo.push(extra.clone());
+                extended_combo
+            }
+        });
+        iter::once(combo).chain(extended)
+    });
+    let combinations: Box<dyn Iterator<Item = Vec<String>> + '_> =
+        if project.configs.strategy == Strategy::Isolated {
+            Box::new(isolated_combinations(project))
+        } else {
+            Box::new(extras_only.chain(strict))
+        };
+    combinations
+        .filter(move |combo| {
+            let breaks = breaks_exclusive_group(project, combo);
+            if breaks {
+                debug!(?combo, "dropped combination enabling exclusive features");
+            }
+            !breaks
+        })
+        .map(move |combo| with_required(project, combo))
+        .inspect(|combo| trace!(?combo, "generated combination"))
+}
+
+/// Generates the combinations of the isolated strategy: no features, each
+/// strict and extra feature alone, then all of them together but the ones
+/// enabling a feature of an exclusive group along with an earlier one.
+fn isolated_combinations(project: &RustProject) -> impl Iterator<Item = Vec<String>> + '_ {
+    let max_size = project.configs.max_combination_size;
+    let fits = move |len: usize| max_size.is_none_or(|max| len <= max);
+    let defaults = enabled_by_default(project);
+    let features: Vec<&String> = project
+        .features
+        .iter()
+        .chain(&project.extra_features)
+        .filter(|feature| !defaults.contains(feature))
+        .collect();
+    let together: Vec<String> = features.iter().fold(Vec::new(), |mut kept, &feature| {
+        kept.push(feature.clone());
+        if breaks_exclusive_group(project, &kept) {
+            kept.pop();
+        }
+        kept
+    });
+    let together = (together.len() > 1 && fits(together.len())).then_some(together);
+    let alone: Vec<Vec<String>> = features
+        .into_iter()
+        .filter(move |_| fits(1))
+        .map(|feature| vec![feature.clone()])
+        .collect();
+    iter::once(vec![NO_DEFAULT_FEATURES.to_string()])
+        .chain(alone)
+        .chain(together)
+}
+
+/// Returns whether `a` and `b` belong to the same exclusive group.
+fn exclusive(project: &RustProject, a: &String, b: &String) -> bool {
+    a != b
+        && project
+            .configs
+            .exclusive
+            .iter()
+            .any(|group| group.contains(a) && group.contains(b))
+}
+
+/// Returns whether `combo`, along with the features it implies, enables two
+/// features of the same exclusive group.
+fn breaks_exclusive_group(project: &RustProject, combo: &[String]) -> bool {
+    broken_exclusive_group(project, combo).is_some()
+}
+
+/// Returns the features of the first exclusive group that `combo`, along
+/// with the features it implies, enables more than one of.
+fn broken_exclusive_group<'a>(
+    project: &'a RustProject,
+    combo: &[String],
+) -> Option<Vec<&'a String>> {
+    if project.configs.exclusive.is_empty() {
+        return None;
+    }
+    let mut enabled: HashSet<&String> = combo.iter().collect();
+    enabled.extend(enabled_by_default(project));
+    for feature in combo {
+        enabled.extend(implied_features(project, feature));
+    }
+    project.configs.exclusive.iter().find_map(|group| {
+        let enabled: Vec<&String> = group.iter().filter(|f| enabled.contains(f)).collect();
+        (enabled.len() > 1).then_some(enabled)
+    })
+}
+
+/// Iterates over the non-empty subsets of `0..n` with at most `max_size`
+/// elements as sorted indices, smallest subsets first.
+struct Subsets {
+    n: usize,
+    max_size: usize,
+    current: Vec<usize>,
+    done: bool,
+}
+
+impl Subsets {
+    fn new(n: usize, max_size: usize) -> Self {
+        Self {
+            n,
+            max_size: max_size.min(n),
+            current: Vec::new(),
+            done: max_size == 0 || n == 0,
+        }
+    }
+}
+
+impl Iterator for Subsets {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        if self.done {
+            return None;
+        }
+        let k = self.current.len();
+        // Advance to the next subset of the same size, or to the first one of the next size.
+        match (0..k).rev().find(|&i| self.current[i] < self.n - k + i) {
+            Some(i) => {
+                self.current[i] += 1;
+                for j in i + 1..k {
+                    self.current[j] = self.current[j - 1] + 1;
+                }
+            }
+            None if k < self.max_size => self.current = (0..=k).collect(),
+            None => {
+                self.done = true;
+                return None;
+            }
+        }
+        Some(self.current.clone())
+    }
+}
+
+/// Returns the indices of the selected strict features, dropping those already
+/// enabled, directly or transitively, by another selected feature. `implied`
+/// holds the features implied by each strict feature.
+///
+/// When selected features enable each other, the first one is kept.
+fn exclude_dependencies(
+    project: &RustProject,
+    implied: &[HashSet<&String>],
+    selection: &[usize],
+) -> Vec<usize> {
+    selection
+        .iter()
+        .enumerate()
+        .filter(|&(pos, &i)| {
+            let feature = &project.features[i];
+            !selection.iter().enumerate().any(|(other_pos, &j)| {
+                j != i
+                    && implied[j].contains(feature)
+                    && (other_pos < pos || !implied[i].contains(&project.features[j]))
+            })
+        })
+        .map(|(_, &i)| i)
+        .collect()
+}
+
+/// Returns the tested features, strict or extra, that none of `combinations`
+/// contains, sorted: exclusive groups, `requires` or `max_combination_size`
+/// may leave a feature out of every combination. The features enabled by
+/// default with `keep_default_features` are always built and those skipped
+/// with `--skip-feature` are left out on purpose, they are never reported.
+pub fn uncovered_features(
+    project: &RustProject,
+    combinations: impl IntoIterator<Item = Vec<String>>,
+) -> Vec<String> {
+    let by_default = enabled_by_default(project);
+    let mut uncovered: BTreeSet<&String> = project
+        .features
+        .iter()
+        .chain(&project.extra_features)
+        .filter(|feature| {
+            !by_default.contains(feature) && !project.configs.skip_features.contains(feature)
+        })
+        .collect();
+    for combo in combinations {
+        if uncovered.is_empty() {
+            break;
+        }
+        for feature in &combo {
+            uncovered.remove(feature);
+        }
+    }
+    uncovered.into_iter().cloned().collect()
+}
+
+/// Explains whether `combo`, a feature or a combination of features, is
+/// generated for `project` and why it isn't, one line each: features the
+/// combinations never list, exclusive groups, size caps, the strategy and
+/// filters. The last line counts the generated combinations containing it.
+pub fn explain(project: &RustProject, combo: &[String]) -> Vec<String> {
+    let configs = &project.configs;
+    let wanted = with_required(project, combo.to_vec());
+    let mut lines = Vec::new();
+    if wanted.len() > combo.len() {
+        lines.push(format!(
+            "requires adds {} to it",
+            wanted[combo.len()..].join(", ")
+        ));
+    }
+
+    let wanted: BTreeSet<&String> = wanted.iter().collect();
+    let mut generated = false;
+    let mut containing = 0;
+    for generated_combo in filter_combinations(configs, generate_combinations(project)) {
+        generated |= generated_combo.iter().collect::<BTreeSet<_>>() == wanted;
+        if combo
+            .iter()
+            .all(|feature| generated_combo.contains(feature))
+        {
+            containing += 1;
+        }
+    }
+
+    let name = format!("{:?}", combo.join(" "));
+    if generated {
+        lines.insert(0, format!("{} is generated", name));
+    } else {
+        lines.insert(0, format!("{} is not generated:", name));
+        let reasons = not_generated_reasons(project, combo);
+        if reasons.is_empty() {
+            lines.push(
+                "it builds the same as a smaller combination, which is generated instead"
+                    .to_string(),
+            );
+        }
+        lines.extend(reasons);
+    }
+    lines.push(format!(
+        "{} generated combinations contain {}",
+        containing, name
+    ));
+    lines
+}
+
+/// Lists the rules of the generation that leave out `combo`.
+fn not_generated_reasons(project: &RustProject, combo: &[String]) -> Vec<String> {
+    let configs = &project.configs;
+    let defaults = enabled_by_default(project);
+    let mut reasons = Vec::new();
+    for feature in combo {
+        if !project.features.contains(feature) && !project.extra_features.contains(feature) {
+            reasons.push(format!("{} is not a tested feature", feature));
+        } else if defaults.contains(feature) {
+            reasons.push(format!(
+                "{} is enabled by default with keep_default_features, combinations never list it",
+                feature
+            ));
+        }
+        if let Some(other) = combo
+            .iter()
+            .find(|other| *other != feature && implied_features(project, other).contains(feature))
+        {
+            reasons.push(format!(
+                "{} is enabled by {}, combinations don't list both",
+                feature, other
+            ));
+        }
+    }
+    let extras: Vec<&str> = combo
+        .iter()
+        .filter(|feature| project.extra_features.contains(feature))
+        .map(String::as_str)
+        .collect();
+    if extras.len() > 1 && configs.strategy != Strategy::Isolated {
+        reasons.push(format!(
+            "{} are extra features, only one is added at a time",
+            extras.join(", ")
+        ));
+    }
+    if let Some(group) = broken_exclusive_group(project, combo) {
+        let group: Vec<&str> = group.iter().map(|feature| feature.as_str()).collect();
+        reasons.push(format!(
+            "it enables {} of the same exclusive group",
+            group.join(" and ")
+        ));
+    }
+    if let Some(max) = configs
+        .max_combination_size
+        .filter(|&max| combo.len() > max)
+    {
+        reasons.push(format!(
+            "it has {} features, more than max_combination_size ({})",
+            combo.len(),
+            max
+        ));
+    }
+    match configs.strategy {
+        Strategy::Full => {}
+        Strategy::Pairwise => reasons.push(
+            "the pairwise strategy only generates enough combinations of the strict features to cover every pair"
+                .to_string(),
+        ),
+        Strategy::Isolated => reasons.push(
+            "the isolated strategy only checks each feature alone and all of them together"
+                .to_string(),
+        ),
+    }
+    for feature in &configs.only_features {
+        if !combo.contains(feature) {
+            reasons.push(format!("--only-feature {} is missing from it", feature));
+        }
+    }
+    for feature in &configs.skip_features {
+        if combo.contains(feature) {
+            reasons.push(format!("--skip-feature {} drops it", feature));
+        }
+    }
+    if let Some(changed) = &configs.changed_features {
+        if !combo.iter().any(|feature| changed.contains(feature)) {
+            reasons.push("none of its features is affected by the changes of --since".to_string());
+        }
+    }
+    reasons
+}
+
+/// Returns the features enabled on top of every combination with
+/// `keep_default_features`: `default` and the features it enables, directly or
+/// transitively. None otherwise, `--no-default-features` turning them off.
+fn enabled_by_default(project: &RustProject) -> HashSet<&String> {
+    let Some((default, _)) = project
+        .dependencies
+        .get_key_value("default")
+        .filter(|_| project.configs.keep_default_features)
+    else {
+        return HashSet::new();
+    };
+    let mut enabled = implied_features(project, default);
+    enabled.insert(default);
+    enabled
+}
+
+/// Returns every feature enabled by `feature`, following feature-enables-feature
+/// edges transitively. `feature` itself is only included on a cycle.
+fn implied_features<'a>(project: &'a RustProject, feature: &str) -> HashSet<&'a String> {
+    let mut implied = HashSet::new();
+    let mut stack: Vec<&String> = enabled_by(project, feature).collect();
+    while let Some(next) = stack.pop() {
+        if implied.insert(next) {
+            stack.extend(enabled_by(project, next));
+        }
+    }
+    implied
+}
+
+/// Returns the features directly enabled by `feature`, in `Cargo.toml` or
+/// through the `requires` constraints of the configuration.
+fn enabled_by<'a>(project: &'a RustProject, feature: &str) -> impl Iterator<Item = &'a String> {
+    let dependencies = project.dependencies.get(feature).into_iter().flatten();
+    let required = project.configs.requires.get(feature).into_iter().flatten();
+    dependencies.chain(required)
+}
+
+/// Appends to `combo` the features required by its features, directly or
+/// transitively, as declared in `requires`: unlike those of `Cargo.toml`, cargo
+/// wouldn't enable them on its own.
+fn with_required(project: &RustProject, mut combo: Vec<String>) -> Vec<String> {
+    if project.configs.requires.is_empty() {
+        return combo;
+    }
+    let mut required = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack: Vec<&String> = combo.iter().collect();
+    while let Some(feature) = stack.pop() {
+        if !visited.insert(feature) {
+            continue;
+        }
+        for requirement in project.configs.requires.get(feature).into_iter().flatten() {
+            if !combo.contains(requirement) && !required.contains(requirement) {
+                required.push(requirement.clone());
+            }
+        }
+        stack.extend(enabled_by(project, feature));
+    }
+    required.sort();
+    combo.extend(required);
+    combo
+}
+
+/// Keeps the combinations containing every feature of `only_features`, none
+/// of `skip_features` and, with `--since`, one of `changed_features`.
+pub fn filter_combinations<'a>(
+    configs: &'a GlobalConfig,
+    combinations: impl IntoIterator<Item = Vec<String>> + 'a,
+) -> impl Iterator<Item = Vec<String>> + 'a {
+    combinations
+        .into_iter()
+        .filter(move |combo| is_selected(configs, combo))
+}
+
+/// Whether `combo` passes the `only_features`, `skip_features` and
+/// `changed_features` filters of [`filter_combinations`].
+pub fn is_selected(configs: &GlobalConfig, combo: &[String]) -> bool {
+    configs.only_features.iter().all(|f| combo.contains(f))
+        && !configs.skip_features.iter().any(|f| combo.contains(f))
+        && configs
+            .changed_features
+            .as_ref()
+            .is_none_or(|changed| changed.iter().any(|f| combo.contains(f)))
+}
+
+/// Combination standing for a `--all-features` run.
+pub const ALL_FEATURES: &str = "--all-features";
+
+/// Combination standing for a `--no-default-features` run without any feature.
+pub const NO_DEFAULT_FEATURES: &str = "--no-default-features";
+
+/// Returns the default-features (an empty combination) and all-features
+/// runs enabled in `configs`, none when `--since` found no changed feature.
+pub fn special_combinations(configs: &GlobalConfig) -> Vec<Vec<String>> {
+    let mut combinations = Vec::new();
+    if configs.changed_features.as_ref().is_some_and(Vec::is_empty) {
+        return combinations;
+    }
+    if configs.test_default_features {
+        combinations.push(Vec::new());
+    }
+    if configs.test_all_features {
+        combinations.push(vec![ALL_FEATURES.to_string()]);
+    }
+    combinations
+}
+
+/// Counts the combinations of `n` strict features, each optionally extended by
+/// one of `extras` extra features, keeping only those of at most `max_size`
+/// features.
+pub fn count_combinations(n: usize, extras: usize, max_size: Option<usize>) -> u64 {
+    let Some(max_size) = max_size else {
+        // Saturates rather than overflowing for 64 features or more.
+        let subsets = u32::try_from(n)
+            .ok()
+            .and_then(|n| 1u64.checked_shl(n))
+            .unwrap_or(u64::MAX);
+        return subsets.saturating_mul(extras as u64 + 1);
+    };
+
+    let mut total = 0u64;
+    let mut subsets_of_size = 1u64; // C(n, 0)
+    for size in 0..=n.min(max_size) {
+        let variants = if size < max_size { extras + 1 } else { 1 };
+        total = total.saturating_add(subsets_of_size.saturating_mul(variants as u64));
+        if total == u64::MAX {
+            break;
+        }
+        // C(n, size + 1), in u128 so that the product doesn't overflow before the division.
+        let next = u128::from(subsets_of_size) * (n - size) as u128 / (size + 1) as u128;
+        subsets_of_size = u64::try_from(next).unwrap_or(u64::MAX);
+    }
+    total
+}
+
+/// Returns about how many combinations `project` generates with its strategy,
+/// without generating them.
+pub fn planned_combinations(project: &RustProject) -> u64 {
+    let extras = project.extra_features.len() as u64;
+    match project.configs.strategy {
+        Strategy::Full => count_combinations(
+            project.features.len(),
+            project.extra_features.len(),
+            project.configs.max_combination_size,
+        ),
+        Strategy::Pairwise => {
+            let rows = pairwise_selections(project.features.len()).len() as u64;
+            rows.saturating_mul(extras + 1).saturating_add(extras)
+        }
+        Strategy::Isolated => {
+            let n = project.features.len() as u64 + extras;
+            n + 1 + u64::from(n > 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{global_config, strings};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn project(features: &[&str], extra: &[&str], dependencies: &[(&str, &[&str])]) -> RustProject {
+        RustProject {
+            hash: 0,
+            configs: global_config(),
+            path: PathBuf::new(),
+            manifest_path: PathBuf::new(),
+            target_dir: PathBuf::new(),
+            features: strings(features),
+            extra_features: strings(extra),
+            dependencies: dependencies
+                .iter()
+                .map(|(feature, deps)| (feature.to_string(), strings(deps)))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    fn combinations(project: &RustProject) -> HashSet<Vec<String>> {
+        generate_combinations(project).collect()
+    }
+
+    fn set(combinations: &[&[&str]]) -> HashSet<Vec<String>> {
+        combinations.iter().map(|combo| strings(combo)).collect()
+    }
+
+    #[test]
+    fn features_without_dependencies_produce_every_subset() {
+        let project = project(&["a", "b"], &["x"], &[]);
+
+        assert_eq!(
+            combinations(&project),
+            set(&[
+                &["a"],
+                &["a", "x"],
+                &["b"],
+                &["b", "x"],
+                &["a", "b"],
+                &["a", "b", "x"],
+                &["x"],
+            ])
+        );
+    }
+
+    #[test]
+    fn dependency_is_dropped_when_its_dependent_is_selected() {
+        let project = project(&["a", "b"], &[], &[("a", &["b"]), ("b", &[])]);
+
+        assert_eq!(combinations(&project), set(&[&["a"], &["b"]]));
+    }
+
+    #[test]
+    fn implied_feature_is_not_generated_alongside_its_dependent() {
+        let project = project(&["a", "b"], &["x"], &[("a", &["b"]), ("b", &[])]);
+
+        let combinations: Vec<_> = generate_combinations(&project).collect();
+
+        assert_eq!(
+            combinations
+                .iter()
+                .filter(|combo| **combo == strings(&["a"]))
+                .count(),
+            1
+        );
+        assert!(!combinations
+            .iter()
+            .any(|combo| combo.contains(&"a".to_string()) && combo.contains(&"b".to_string())));
+    }
+
+    #[test]
+    fn dependency_chain_drops_transitively_enabled_features() {
+        let project = project(
+            &["a", "b", "c"],
+            &[],
+            &[("a", &["b"]), ("b", &["c"]), ("c", &[])],
+        );
+
+        // `a` enables `c` through `b`, so `{a, c}` is the same as `{a}`.
+        assert_eq!(combinations(&project), set(&[&["a"], &["b"], &["c"]]));
+    }
+
+    #[test]
+    fn implication_through_untested_feature_is_followed() {
+        let project = project(
+            &["a", "c"],
+            &[],
+            &[("a", &["hidden"]), ("hidden", &["c"]), ("c", &[])],
+        );
+
+        assert_eq!(combinations(&project), set(&[&["a"], &["c"]]));
+    }
+
+    #[test]
+    fn features_enabling_each_other_keep_the_first() {
+        let project = project(&["a", "b"], &[], &[("a", &["b"]), ("b", &["a"])]);
+
+        assert_eq!(combinations(&project), set(&[&["a"], &["b"]]));
+    }
+
+    #[test]
+    fn filters_keep_only_and_drop_skipped_features() {
+        let mut project = project(&["a", "b", "c"], &[], &[]);
+        project.configs.only_features = strings(&["a"]);
+        project.configs.skip_features = strings(&["c"]);
+
+        let filtered = filter_combinations(&project.configs, combinations(&project));
+
+        assert_eq!(
+            filtered.collect::<HashSet<_>>(),
+            set(&[&["a"], &["a", "b"]])
+        );
+    }
+
+    #[test]
+    fn changed_features_keep_combinations_containing_one() {
+        let mut project = project(&["a", "b", "c"], &[], &[]);
+        project.configs.test_default_features = true;
+        project.configs.changed_features = Some(strings(&["b"]));
+
+        let filtered = filter_combinations(&project.configs, combinations(&project));
+
+        assert_eq!(
+            filtered.collect::<HashSet<_>>(),
+            set(&[&["b"], &["a", "b"], &["b", "c"], &["a", "b", "c"]])
+        );
+        assert_eq!(special_combinations(&project.configs).len(), 1);
+        project.configs.changed_features = Some(Vec::new());
+        assert!(special_combinations(&project.configs).is_empty());
+    }
+
+    #[test]
+    fn combinations_are_unique_and_smallest_first() {
+        let mut project = project(&["a", "b", "c", "d"], &["x"], &[("a", &["b"])]);
+        project.configs.max_combination_size = Some(3);
+
+        let combinations: Vec<_> = generate_combinations(&project).collect();
+
+        let unique: HashSet<_> = combinations.iter().collect();
+        assert_eq!(unique.len(), combinations.len());
+        let strict_sizes: Vec<usize> = combinations
+            .iter()
+            .filter(|combo| !combo.contains(&"x".to_string()))
+            .map(Vec::len)
+            .collect();
+        assert!(strict_sizes.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(combinations.iter().all(|combo| combo.len() <= 3));
+    }
+
+    #[test]
+    fn generation_order_is_reproducible() {
+        // Each project gets its own randomly seeded hash maps.
+        let build = || {
+            let mut project = project(
+                &["a", "b", "c", "d"],
+                &["x", "y"],
+                &[("d", &["a"]), ("y", &["b"])],
+            );
+            project.configs.requires = HashMap::from([
+                ("c".to_string(), strings(&["b", "a"])),
+                ("x".to_string(), strings(&["d"])),
+            ]);
+            project.configs.exclusive = vec![strings(&["b", "x"])];
+            project
+        };
+        let (mut first, mut second) = (build(), build());
+
+        for strategy in [Strategy::Full, Strategy::Pairwise, Strategy::Isolated] {
+            first.configs.strategy = strategy;
+            second.configs.strategy = strategy;
+            let first: Vec<_> = generate_combinations(&first).collect();
+            let second: Vec<_> = generate_combinations(&second).collect();
+
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn exclusive_features_are_never_combined() {
+        let mut project = project(
+            &["a", "rt-async-std", "rt-tokio", "tokio-full"],
+            &["x", "y"],
+            &[("tokio-full", &["rt-tokio"])],
+        );
+        project.configs.exclusive =
+            vec![strings(&["rt-tokio", "rt-async-std"]), strings(&["x", "y"])];
+
+        for strategy in [Strategy::Full, Strategy::Pairwise, Strategy::Isolated] {
+            project.configs.strategy = strategy;
+            let combinations = combinations(&project);
+
+            assert!(combinations
+                .iter()
+                .any(|combo| combo.contains(&"rt-tokio".to_string())));
+            assert!(combinations
+                .iter()
+                .any(|combo| combo.contains(&"rt-async-std".to_string())));
+            for combo in &combinations {
+                let has = |feature: &str| combo.contains(&feature.to_string());
+                assert!(
+                    !(has("rt-async-std") && (has("rt-tokio") || has("tokio-full"))),
+                    "{:?}",
+                    combo
+                );
+                assert!(!(has("x") && has("y")), "{:?}", combo);
+            }
+        }
+    }
+
+    #[test]
+    fn features_enabled_by_default_are_not_combined_on_top_of_the_defaults() {
+        let mut project = project(
+            &["alloc", "std", "tls"],
+            &["no-std"],
+            &[
+                ("default", &["std"]),
+                ("std", &["alloc"]),
+                ("alloc", &[]),
+                ("tls", &[]),
+                ("no-std", &[]),
+            ],
+        );
+        project.configs.exclusive = vec![strings(&["std", "no-std"])];
+
+        let combinations_without_defaults = combinations(&project);
+        assert!(combinations_without_defaults.contains(&strings(&["std", "tls"])));
+        assert!(combinations_without_defaults.contains(&strings(&["no-std"])));
+
+        project.configs.keep_default_features = true;
+        for strategy in [Strategy::Full, Strategy::Pairwise, Strategy::Isolated] {
+            project.configs.strategy = strategy;
+            let combinations = combinations(&project);
+
+            assert!(combinations.contains(&strings(&["tls"])), "{:?}", strategy);
+            for combo in &combinations {
+                assert!(
+                    !combo
+                        .iter()
+                        .any(|feature| ["alloc", "std", "no-std"].contains(&feature.as_str())),
+                    "{:?}",
+                    combo
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn features_left_out_of_every_combination_are_uncovered() {
+        let mut project = project(&["a", "b"], &["c"], &[("a", &[]), ("b", &[]), ("c", &[])]);
+        assert!(uncovered_features(&project, combinations(&project)).is_empty());
+
+        project.configs.requires = HashMap::from([("c".to_string(), strings(&["a"]))]);
+        project.configs.exclusive = vec![strings(&["a", "c"])];
+        assert_eq!(
+            uncovered_features(&project, combinations(&project)),
+            strings(&["c"])
+        );
+
+        project.configs.skip_features = strings(&["c"]);
+        assert!(uncovered_features(&project, combinations(&project)).is_empty());
+    }
+
+    #[test]
+    fn explanations_name_the_rules_dropping_a_combination() {
+        let mut project = project(
+            &["a", "b", "c"],
+            &["x", "y"],
+            &[
+                ("b", &["a"]),
+                ("a", &[]),
+                ("c", &[]),
+                ("x", &[]),
+                ("y", &[]),
+            ],
+        );
+        project.configs.exclusive = vec![strings(&["a", "c"])];
+
+        assert_eq!(
+            explain(&project, &strings(&["b", "x"])),
+            strings(&[
+                "\"b x\" is generated",
+                "1 generated combinations contain \"b x\""
+            ])
+        );
+        let lines = explain(&project, &strings(&["a", "b", "c", "x", "y"]));
+        assert_eq!(lines[0], "\"a b c x y\" is not generated:");
+        for reason in [
+            "a is enabled by b, combinations don't list both",
+            "x, y are extra features, only one is added at a time",
+            "it enables a and c of the same exclusive group",
+        ] {
+            assert!(lines.contains(&reason.to_string()), "{:?}", lines);
+        }
+        assert_eq!(
+            explain(&project, &strings(&["nope"]))[1],
+            "nope is not a tested feature"
+        );
+    }
+
+    #[test]
+    fn required_features_are_added() {
+        let mut project = project(&["alloc", "derive", "std"], &["x"], &[("std", &["alloc"])]);
+        project.configs.requires = HashMap::from([
+            ("derive".to_string(), strings(&["std"])),
+            ("x".to_string(), strings(&["derive"])),
+        ]);
+
+        let combinations: Vec<_> = generate_combinations(&project).collect();
+
+        assert!(combinations.contains(&strings(&["derive", "std"])));
+        assert!(combinations.contains(&strings(&["std"])));
+        assert!(combinations.contains(&strings(&["x", "derive", "std"])));
+        // Every combination with derive has std, and each is generated once.
+        assert!(combinations
+            .iter()
+            .filter(|combo| combo.contains(&"derive".to_string()))
+            .all(|combo| combo.contains(&"std".to_string())));
+        let unique: HashSet<Vec<&String>> = combinations
+            .iter()
+            .map(|combo| {
+                let mut sorted: Vec<&String> = combo.iter().collect();
+                sorted.sort();
+                sorted
+            })
+            .collect();
+        assert_eq!(unique.len(), combinations.len());
+    }
+
+    #[test]
+    fn subsets_cover_every_size_up_to_the_maximum() {
+        let subsets: Vec<_> = Subsets::new(3, 2).collect();
+
+        assert_eq!(
+            subsets,
+            vec![
+                vec![0],
+                vec![1],
+                vec![2],
+                vec![0, 1],
+                vec![0, 2],
+                vec![1, 2]
+            ]
+        );
+        assert_eq!(Subsets::new(4, 4).count(), 15);
+        assert_eq!(Subsets::new(0, 3).count(), 0);
+    }
+
+    #[test]
+    fn count_combinations_respects_max_size() {
+        assert_eq!(count_combinations(3, 2, None), 24);
+        // Enough room for every strict subset plus an extra feature.
+        assert_eq!(count_combinations(3, 2, Some(4)), 24);
+        // 1 + 3 + 3 subsets of size <= 2, those of size <= 1 also with 2 extras.
+        assert_eq!(count_combinations(3, 2, Some(2)), 4 * 3 + 3);
+        assert_eq!(count_combinations(3, 0, Some(1)), 4);
+    }
+
+    #[test]
+    fn count_combinations_saturates() {
+        assert_eq!(count_combinations(63, 0, None), 1 << 63);
+        assert_eq!(count_combinations(63, 1, None), u64::MAX);
+        assert_eq!(count_combinations(64, 0, None), u64::MAX);
+        assert_eq!(count_combinations(70, 0, Some(70)), u64::MAX);
+        // 1 + 70 + C(70, 2) subsets with 2 extras each, plus C(70, 3).
+        assert_eq!(
+            count_combinations(70, 2, Some(3)),
+            (1 + 70 + 2415) * 3 + 54740
+        );
+    }
+
+    #[test]
+    fn seventy_strict_features_do_not_overflow() {
+        let features: Vec<String> = (0..70).map(|i| format!("f{}", i)).collect();
+        let features: Vec<&str> = features.iter().map(String::as_str).collect();
+        let mut project = project(&features, &["x"], &[]);
+
+        assert_eq!(planned_combinations(&project), u64::MAX);
+        assert_eq!(generate_combinations(&project).take(200).count(), 200);
+        project.configs.strategy = Strategy::Pairwise;
+        assert!(planned_combinations(&project) < 1000);
+    }
+
+    #[test]
+    fn isolated_checks_no_features_each_alone_and_all_together() {
+        let mut project = project(&["a", "b", "c"], &["x"], &[]);
+        project.configs.strategy = Strategy::Isolated;
+
+        let combinations: Vec<_> = generate_combinations(&project).collect();
+
+        assert_eq!(
+            combinations,
+            vec![
+                strings(&[NO_DEFAULT_FEATURES]),
+                strings(&["a"]),
+                strings(&["b"]),
+                strings(&["c"]),
+                strings(&["x"]),
+                strings(&["a", "b", "c", "x"]),
+            ]
+        );
+        assert_eq!(planned_combinations(&project), 4 + 2);
+    }
+
+    #[test]
+    fn pairwise_plans_far_fewer_combinations() {
+        let features: Vec<String> = (0..20).map(|i| format!("f{}", i)).collect();
+        let features: Vec<&str> = features.iter().map(String::as_str).collect();
+        let mut project = project(&features, &[], &[]);
+
+        assert_eq!(planned_combinations(&project), 1 << 20);
+        project.configs.strategy = Strategy::Pairwise;
+        assert!(planned_combinations(&project) < 100);
+    }
+}
@@ -1,44 +1,913 @@
+use crate::error::{MultiCheckError, Result};
+use crate::progress::ColorChoice;
+use indicatif::ProgressStyle;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
+use toml::{Table, Value};
+use tracing::warn;
 
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FeatureConfig {
     pub strict: bool,
+    /// Extra cargo arguments of the combinations containing this feature.
+    #[serde(default)]
+    pub cargo_args: Vec<String>,
+}
+
+/// Strict and extra features listed as arrays, instead of one `[features]`
+/// entry each.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Selection {
+    #[serde(default)]
+    pub strict: Vec<String>,
+    #[serde(default)]
+    pub extra: Vec<String>,
+}
+
+/// Cargo subcommand run for each feature combination.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckCommand {
+    #[default]
+    Check,
+    Clippy,
+    Build,
+    Test,
+    Doc,
+}
+
+impl CheckCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckCommand::Check => "check",
+            CheckCommand::Clippy => "clippy",
+            CheckCommand::Build => "build",
+            CheckCommand::Test => "test",
+            CheckCommand::Doc => "doc",
+        }
+    }
+}
+
+/// How strict features are combined into the tested combinations.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Every subset of the strict features.
+    #[default]
+    Full,
+    /// A covering array where every pair of strict features is tested together.
+    Pairwise,
+    /// No features, each feature alone and all of them together.
+    Isolated,
+}
+
+impl Strategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "full" => Some(Strategy::Full),
+            "pairwise" => Some(Strategy::Pairwise),
+            "isolated" => Some(Strategy::Isolated),
+            _ => None,
+        }
+    }
+}
+
+/// Step run once before the checks.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Warmup {
+    /// Nothing, dependencies are fetched and built by the first checks.
+    None,
+    /// `cargo fetch`, which downloads the dependencies without building them.
+    #[default]
+    Fetch,
+    /// `cargo build --all-features`, which fails when some features are incompatible.
+    BuildAll,
 }
 
 #[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct GlobalConfig {
+    /// Number of checks run in parallel, one per logical CPU when unset.
+    #[serde(default = "default_concurrency")]
     pub concurrency: usize,
+    /// Jobs of each cargo command, passed as `--jobs`. Unset shares the logical
+    /// CPUs between the `concurrency` checks.
+    pub cargo_jobs: Option<usize>,
     pub clean: bool,
     pub clear_terminal: bool,
+    /// Cargo subcommand run for each combination, `check` when neither this nor `commands` is set.
+    pub command: Option<CheckCommand>,
+    /// Cargo subcommands run in order for each combination, which only passes if they all do.
+    #[serde(default)]
+    pub commands: Vec<CheckCommand>,
+    /// Maximum number of seconds a single check may run before it is killed.
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Largest number of features tested together in a single combination.
+    pub max_combination_size: Option<usize>,
+    /// Number of times a failing check is run again before it counts as failed.
+    #[serde(default)]
+    pub retries: usize,
+    /// Stop at the first failing combination instead of running every check.
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Fail the combinations that compile with warnings, by adding `-D warnings` to `RUSTFLAGS`.
+    #[serde(default)]
+    pub deny_warnings: bool,
+    /// Environment variables set on every cargo invocation, `RUSTFLAGS` and
+    /// `RUSTDOCFLAGS` are appended to the inherited ones.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Groups of features that can't be enabled together, combinations
+    /// enabling two features of a group are never generated.
+    #[serde(default)]
+    pub exclusive: Vec<Vec<String>>,
+    /// Features that must be enabled along with each feature, they are added to
+    /// every combination containing it.
+    #[serde(default)]
+    pub requires: HashMap<String, Vec<String>>,
+    /// Step run before the checks, fetching the dependencies by default.
+    #[serde(default)]
+    pub warmup: Warmup,
+    /// Pass `--offline` to the checks, dependencies are only fetched by the warm-up.
+    #[serde(default)]
+    pub offline: bool,
+    /// Pass `--locked` to every cargo invocation.
+    #[serde(default)]
+    pub locked: bool,
+    /// Extra arguments appended to every cargo invocation, in order.
+    #[serde(default)]
+    pub extra_cargo_args: Vec<String>,
+    /// Cargo profile of the checks, `--release` or `--profile <name>`, the dev profile when unset.
+    pub profile: Option<String>,
+    /// Target triple passed to cargo with `--target`, the host when unset.
+    pub target: Option<String>,
+    /// Cargo binary to run, `$CARGO` or `cargo` from `PATH` when unset.
+    pub cargo_bin: Option<String>,
+    /// Rustup toolchain used for every cargo invocation, as in `cargo +<toolchain>`.
+    pub toolchain: Option<String>,
+    /// Stream cargo's output to the terminal instead of capturing it.
+    #[serde(default)]
+    pub verbose: bool,
+    /// Give each concurrent worker its own `--target-dir` so checks don't
+    /// wait on each other's build lock.
+    #[serde(default)]
+    pub isolated_target_dirs: bool,
+    /// Check the combinations on top of the default features, without `--no-default-features`.
+    #[serde(default)]
+    pub keep_default_features: bool,
+    /// Also run cargo once with the default features.
+    #[serde(default)]
+    pub test_default_features: bool,
+    /// Also run cargo once with `--all-features`.
+    #[serde(default)]
+    pub test_all_features: bool,
+    /// Skip combinations containing every feature of a combination that already failed.
+    #[serde(default)]
+    pub prune_supersets: bool,
+    /// Exit with 0 even when some combinations fail, the reports still list them as failed.
+    #[serde(default)]
+    pub allow_failures: bool,
+    /// Combinations known to fail, in any order: their failures are reported
+    /// as ignored and don't fail the run.
+    #[serde(default)]
+    pub ignore: Vec<Vec<String>>,
+    /// Shrink each failing combination to a minimal failing set of features.
+    #[serde(default)]
+    pub minimize: bool,
+    /// Maximum number of extra checks run to minimize failing combinations, over the whole run.
+    #[serde(default = "default_minimize_budget")]
+    pub minimize_budget: usize,
+    /// Refuse to start a run planning more combinations than this, unless confirmed.
+    #[serde(default = "default_max_combinations")]
+    pub max_combinations: u64,
+    /// Start runs above `max_combinations` without asking for `--yes`.
+    #[serde(default)]
+    pub allow_many_combinations: bool,
+    /// Warn about the features of `Cargo.toml` missing from the configuration.
+    #[serde(default = "default_true")]
+    pub warn_untested_features: bool,
+    /// `indicatif` template of the bar tracking the checked combinations.
+    pub progress_template: Option<String>,
+    /// `indicatif` template of the spinner of each worker, `{prefix}` being the worker number.
+    pub spinner_template: Option<String>,
+    /// Read the features from `cargo metadata` rather than parsing `Cargo.toml`.
+    #[serde(default)]
+    pub use_cargo_metadata: bool,
+    /// Extra cargo arguments of the combinations containing each feature,
+    /// from the `cargo_args` of `[features]`.
+    #[serde(skip)]
+    pub feature_cargo_args: BTreeMap<String, Vec<String>>,
+    /// Manifest passed to every cargo invocation, set from `--manifest-path`.
+    #[serde(skip)]
+    pub manifest_path: Option<PathBuf>,
+    /// Workspace member to check, set from `--package`.
+    #[serde(skip)]
+    pub package: Option<String>,
+    /// Only keep combinations containing all of these features, set from `--only-feature`.
+    #[serde(skip)]
+    pub only_features: Vec<String>,
+    /// Drop combinations containing any of these features, set from `--skip-feature`.
+    #[serde(skip)]
+    pub skip_features: Vec<String>,
+    /// Only keep combinations containing one of these features, the features
+    /// affected by the changes since `--since`.
+    #[serde(skip)]
+    pub changed_features: Option<Vec<String>>,
+    /// Print plain progress lines instead of drawing progress bars, set from `--no-progress`.
+    #[serde(skip)]
+    pub no_progress: bool,
+    /// Directory the output of every check is written to, created for the run
+    /// from `--log-dir`.
+    #[serde(skip)]
+    pub log_dir: Option<PathBuf>,
+    /// Only print the failing combinations, set from `--quiet`.
+    #[serde(skip)]
+    pub quiet: bool,
+    /// Whether the progress is colored, set from `--color`.
+    #[serde(skip)]
+    pub color: ColorChoice,
+}
+
+impl GlobalConfig {
+    /// Returns the `--jobs` of each cargo command, at least 1.
+    pub fn cargo_jobs(&self) -> usize {
+        self.cargo_jobs
+            .unwrap_or_else(|| default_concurrency() / self.concurrency.max(1))
+            .max(1)
+    }
+
+    /// Returns whether `combo` is listed in `ignore`, whatever the order of its features.
+    pub fn is_ignored(&self, combo: &[String]) -> bool {
+        let mut combo = combo.to_vec();
+        combo.sort();
+        combo.dedup();
+        self.ignore.iter().any(|ignored| {
+            let mut ignored = ignored.clone();
+            ignored.sort();
+            ignored.dedup();
+            ignored == combo
+        })
+    }
+
+    /// Returns the cargo subcommands run for each combination, in order.
+    pub fn commands(&self) -> Vec<CheckCommand> {
+        if self.commands.is_empty() {
+            vec![self.command.unwrap_or_default()]
+        } else {
+            self.commands.clone()
+        }
+    }
 }
 
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub global: GlobalConfig,
+    #[serde(default)]
     pub features: HashMap<String, FeatureConfig>,
+    /// The tested features as arrays, exclusive with `features`.
+    pub selection: Option<Selection>,
+    /// Files and directories, relative to the project, whose changes affect
+    /// each feature with `--since`.
+    #[serde(default)]
+    pub paths: HashMap<String, Vec<PathBuf>>,
 }
 
+/// Configuration path reading the configuration from stdin.
+pub const STDIN_PATH: &str = "-";
+
 impl Config {
-    pub fn new(file_path: &str) -> io::Result<Self> {
-        let contents = match fs::read_to_string(file_path) {
-            Ok(c) => c,
-            Err(_) => {
-                return Err(io::Error::new(io::ErrorKind::NotFound, "File not found"));
-            }
-        };
+    /// Loads the configuration from `file_path`, or from stdin when it is `-`.
+    pub fn new(file_path: &str) -> Result<Self> {
+        Self::load(&[file_path])
+    }
+
+    /// Loads the configurations of `paths` merged in order, see [`merge`].
+    /// Each path is a file, `-` for stdin, or a file name pattern with `*`
+    /// and `?` wildcards such as `configs/*.toml`, whose matches are merged
+    /// in alphabetical order.
+    pub fn load(paths: &[&str]) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in paths {
+            files.extend(expand(path)?);
+        }
+        if let [file] = files.as_slice() {
+            // Parsed straight from the text, so that errors point at a line.
+            return Self::parse(&read_config(file)?);
+        }
+        let mut merged = Table::new();
+        for file in &files {
+            let table = read_config(file)?
+                .parse()
+                .map_err(|err| invalid(format!("invalid TOML in {:?}: {}", file, err)))?;
+            merge(&mut merged, table);
+        }
+        let data = Value::Table(merged)
+            .try_into()
+            .map_err(|err| invalid(format!("invalid TOML: {}", err)))?;
+        Self::prepare(data)
+    }
 
-        let data: Self = match toml::from_str(&contents) {
+    /// Parses and validates the TOML configuration in `contents`.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let data: Self = match toml::from_str(contents) {
             Ok(d) => d,
             Err(err) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Invalid TOML: {}", err),
-                ));
+                return Err(invalid(format!("invalid TOML: {}", err)));
             }
         };
+        Self::prepare(data)
+    }
 
+    /// Fills in the defaults depending on the machine and validates `data`.
+    fn prepare(mut data: Self) -> Result<Self> {
+        data.global.feature_cargo_args = data
+            .features
+            .iter()
+            .filter(|(_, feature)| !feature.cargo_args.is_empty())
+            .map(|(name, feature)| (name.clone(), feature.cargo_args.clone()))
+            .collect();
+        data.validate()?;
         Ok(data)
     }
+
+    /// Checks values that deserialize fine but can't be used.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(toolchain) = &self.global.toolchain {
+            if toolchain.trim().trim_start_matches('+').is_empty() {
+                return Err(invalid("toolchain must not be empty"));
+            }
+        }
+
+        if self
+            .global
+            .profile
+            .as_deref()
+            .is_some_and(|p| p.trim().is_empty())
+        {
+            return Err(invalid("profile must not be empty"));
+        }
+
+        for (name, template) in [
+            ("progress_template", &self.global.progress_template),
+            ("spinner_template", &self.global.spinner_template),
+        ] {
+            if let Some(Err(err)) = template.as_deref().map(ProgressStyle::with_template) {
+                return Err(invalid(format!("invalid {}: {}", name, err)));
+            }
+        }
+
+        for (name, value) in [
+            ("concurrency", Some(self.global.concurrency as u64)),
+            ("cargo_jobs", self.global.cargo_jobs.map(|jobs| jobs as u64)),
+            ("timeout_secs", self.global.timeout_secs),
+            (
+                "max_combination_size",
+                self.global.max_combination_size.map(|size| size as u64),
+            ),
+        ] {
+            if value == Some(0) {
+                return Err(invalid(format!("{} must be >= 1", name)));
+            }
+        }
+        for (name, value) in [
+            ("concurrency", Some(self.global.concurrency)),
+            ("cargo_jobs", self.global.cargo_jobs),
+        ] {
+            if value.is_some_and(|value| value > MAX_PARALLELISM) {
+                return Err(invalid(format!("{} must be <= {}", name, MAX_PARALLELISM)));
+            }
+        }
+
+        if self.global.command.is_some() && !self.global.commands.is_empty() {
+            return Err(invalid("command and commands can't both be set"));
+        }
+
+        if let Some(selection) = &self.selection {
+            if !self.features.is_empty() {
+                return Err(invalid(
+                    "features are listed in both [features] and [selection], use only one",
+                ));
+            }
+            if let Some(feature) = selection
+                .strict
+                .iter()
+                .find(|feature| selection.extra.contains(feature))
+            {
+                return Err(invalid(format!(
+                    "feature {} is both strict and extra",
+                    feature
+                )));
+            }
+        }
+
+        if let Some(group) = self.global.exclusive.iter().find(|group| group.len() < 2) {
+            return Err(invalid(format!(
+                "exclusive group {:?} must list at least two features",
+                group
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Highest `concurrency` and `cargo_jobs`, far above any machine's CPU count.
+const MAX_PARALLELISM: usize = 1024;
+
+fn invalid(message: impl Into<String>) -> MultiCheckError {
+    MultiCheckError::InvalidConfig(message.into())
+}
+
+/// Reads the configuration file at `path`, or stdin when it is `-`.
+fn read_config(path: &str) -> Result<String> {
+    if path == STDIN_PATH {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        return Ok(contents);
+    }
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Err(MultiCheckError::ConfigNotFound(PathBuf::from(path)))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Returns the files matching `pattern`, sorted, when its file name has a
+/// `*` or `?` wildcard, else `pattern` itself.
+fn expand(pattern: &str) -> Result<Vec<String>> {
+    let path = Path::new(pattern);
+    let name: Vec<char> = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.contains(['*', '?']) => name.chars().collect(),
+        _ => return Ok(vec![pattern.to_string()]),
+    };
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let not_found = || MultiCheckError::ConfigNotFound(PathBuf::from(pattern));
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(not_found()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let matches = file_name
+            .to_str()
+            .is_some_and(|file_name| wildcard_match(&name, &file_name.chars().collect::<Vec<_>>()));
+        if matches && entry.file_type()?.is_file() {
+            files.push(dir.join(file_name).display().to_string());
+        }
+    }
+    if files.is_empty() {
+        return Err(not_found());
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns whether `name` matches `pattern`, where `*` matches any run of
+/// characters and `?` a single one.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && wildcard_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && wildcard_match(rest, &name[1..]),
+    }
+}
+
+/// Merges the configuration `overlay` into `base`, loaded before it. Keys
+/// of `[global]` are replaced, except for `exclusive` and `ignore` whose
+/// entries are appended and `env` and `requires` which are merged per key.
+/// Entries of `[features]` and `[paths]` are replaced per feature, as is the
+/// list of `[selection]` a feature is in. A feature changing strictness is
+/// warned about.
+fn merge(base: &mut Table, overlay: Table) {
+    for (section, value) in overlay {
+        match (base.get_mut(&section), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => match section.as_str() {
+                "global" => merge_global(base, overlay),
+                "selection" => merge_selection(base, overlay),
+                "features" => {
+                    let strict = |entry: &Value| entry.get("strict").and_then(Value::as_bool);
+                    for (feature, entry) in overlay {
+                        let previous = base.get(&feature).and_then(strict);
+                        if previous.is_some() && previous != strict(&entry) {
+                            warn_strictness_conflict(&feature);
+                        }
+                        base.insert(feature, entry);
+                    }
+                }
+                _ => base.extend(overlay),
+            },
+            (_, value) => {
+                base.insert(section, value);
+            }
+        }
+    }
+}
+
+fn warn_strictness_conflict(feature: &str) {
+    warn!(
+        "Feature {} is strict in one configuration file and not in another, the last one wins",
+        feature
+    );
+}
+
+fn merge_global(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (key.as_str(), base.get_mut(&key), value) {
+            ("exclusive" | "ignore", Some(Value::Array(base)), Value::Array(entries)) => {
+                base.extend(entries)
+            }
+            ("env" | "requires", Some(Value::Table(base)), Value::Table(entries)) => {
+                base.extend(entries)
+            }
+            (_, _, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn merge_selection(base: &mut Table, overlay: Table) {
+    for (list, other) in [("strict", "extra"), ("extra", "strict")] {
+        let Some(Value::Array(features)) = overlay.get(list) else {
+            continue;
+        };
+        if let Some(Value::Array(moved)) = base.get_mut(other) {
+            moved.retain(|feature| {
+                let conflict = features.contains(feature);
+                if conflict {
+                    warn_strictness_conflict(feature.as_str().unwrap_or_default());
+                }
+                !conflict
+            });
+        }
+        match base.get_mut(list) {
+            Some(Value::Array(listed)) => {
+                for feature in features {
+                    if !listed.contains(feature) {
+                        listed.push(feature.clone());
+                    }
+                }
+            }
+            _ => {
+                base.insert(list.to_string(), Value::Array(features.clone()));
+            }
+        }
+    }
+}
+
+/// Returns a starter configuration testing each of `features` as a non-strict feature.
+pub fn template(features: &[String]) -> String {
+    let mut config = String::from(
+        "[global]
+# Number of checks run in parallel, one per logical CPU when unset.
+# concurrency = 4
+# Run `cargo clean` before checking the combinations.
+clean = false
+# Clear the terminal before and after the run.
+clear_terminal = false
+# Cargo subcommand run for each combination: check, clippy, build, test or doc.
+# Use `commands = [\"check\", \"clippy\"]` instead to run several in order.
+command = \"check\"
+# Stop at the first failing combination.
+fail_fast = false
+
+[features]
+# Strict features are tested in every combination with each other, the others
+# are only added one at a time on top of them.
+",
+    );
+    for feature in features {
+        config.push_str(&format!("{} = {{ strict = false }}\n", toml_key(feature)));
+    }
+    config
+}
+
+/// Returns `key` as a TOML key, quoting it when it isn't a valid bare key.
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_minimize_budget() -> usize {
+    50
+}
+
+fn default_max_combinations() -> u64 {
+    1024
+}
+
+/// Returns the number of logical CPUs, or 1 if it can't be determined.
+fn default_concurrency() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn load(contents: &str) -> Result<Config> {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        Config::new(file.path().to_str().unwrap())
+    }
+
+    #[test]
+    fn concurrency_defaults_to_cpu_count() {
+        let omitted =
+            load("[global]\nclean = false\nclear_terminal = false\n[features]\n").unwrap();
+
+        assert_eq!(omitted.global.concurrency, default_concurrency());
+        assert!(default_concurrency() >= 1);
+        assert_eq!(
+            omitted.global.cargo_jobs(),
+            (default_concurrency() / omitted.global.concurrency).max(1)
+        );
+    }
+
+    #[test]
+    fn parsed_configuration_matches_loaded_file() {
+        let contents = "[global]\nconcurrency = 3\nclean = true\nclear_terminal = false\n\
+                        command = \"clippy\"\n[features]\na = { strict = true }\n";
+
+        let loaded = load(contents).unwrap();
+        let parsed = Config::parse(contents).unwrap();
+
+        assert_eq!(parsed.global.concurrency, loaded.global.concurrency);
+        assert_eq!(parsed.global.clean, loaded.global.clean);
+        assert_eq!(parsed.global.command, loaded.global.command);
+        assert_eq!(parsed.features.len(), loaded.features.len());
+        assert!(parsed.features["a"].strict);
+    }
+
+    #[test]
+    fn template_loads_with_every_feature_non_strict() {
+        let features = vec!["a".to_string(), "with-dash".to_string(), "v1.2".to_string()];
+
+        let config = load(&template(&features)).unwrap();
+
+        assert_eq!(config.features.len(), 3);
+        assert!(features.iter().all(|f| !config.features[f].strict));
+        assert_eq!(config.global.commands(), vec![CheckCommand::Check]);
+    }
+
+    #[test]
+    fn paths_section_maps_features_to_paths() {
+        let config = load(
+            "[global]\nclean = false\nclear_terminal = false\n[features]\ntls = { strict = true }\n\
+             [paths]\ntls = [\"src/tls.rs\", \"src/tls/\"]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.paths["tls"],
+            vec![PathBuf::from("src/tls.rs"), PathBuf::from("src/tls/")]
+        );
+    }
+
+    #[test]
+    fn commands_replace_the_single_command() {
+        let config = load(
+            "[global]\nclean = false\nclear_terminal = false\ncommands = [\"check\", \"test\"]\n[features]\n",
+        )
+        .unwrap();
+        let both = load(
+            "[global]\nclean = false\nclear_terminal = false\ncommand = \"check\"\ncommands = [\"test\"]\n[features]\n",
+        );
+
+        assert_eq!(
+            config.global.commands(),
+            vec![CheckCommand::Check, CheckCommand::Test]
+        );
+        assert!(matches!(both, Err(MultiCheckError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn zero_values_are_rejected_with_their_field() {
+        let global = "[global]\nclean = false\nclear_terminal = false\n";
+        let err = load(&format!("{}timeout_secs = 0\n[features]\n", global))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration: timeout_secs must be >= 1"
+        );
+
+        let err = load(&format!("{}concurrency = 0\n[features]\n", global))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration: concurrency must be >= 1"
+        );
+    }
+
+    #[test]
+    fn absurd_parallelism_is_rejected() {
+        let global = "[global]\nclean = false\nclear_terminal = false\n";
+        let err = load(&format!("{}concurrency = 100000\n[features]\n", global))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration: concurrency must be <= 1024"
+        );
+        let err = load(&format!("{}cargo_jobs = 1025\n[features]\n", global))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration: cargo_jobs must be <= 1024"
+        );
+        assert!(load(&format!("{}concurrency = 1024\n[features]\n", global)).is_ok());
+    }
+
+    #[test]
+    fn later_files_override_earlier_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path.display().to_string()
+        };
+        let base = write(
+            "base.toml",
+            "[global]\nconcurrency = 2\nclean = false\nclear_terminal = false\n\
+             ignore = [[\"a\"]]\nenv = { A = \"1\", B = \"1\" }\n\
+             [features]\na = { strict = true }\nb = { strict = true }\n",
+        );
+        let overlay = write(
+            "crate.toml",
+            "[global]\nconcurrency = 4\nignore = [[\"b\"]]\nenv = { B = \"2\" }\n\
+             [features]\nb = { strict = false }\nc = { strict = true }\n",
+        );
+
+        let config = Config::load(&[&base, &overlay]).unwrap();
+
+        assert_eq!(config.global.concurrency, 4);
+        assert!(!config.global.clean);
+        assert_eq!(config.global.ignore, vec![vec!["a"], vec!["b"]]);
+        assert_eq!(config.global.env["A"], "1");
+        assert_eq!(config.global.env["B"], "2");
+        assert!(config.features["a"].strict);
+        assert!(!config.features["b"].strict);
+        assert!(config.features["c"].strict);
+
+        let pattern = dir.path().join("*.toml").display().to_string();
+        let globbed = Config::load(&[&pattern]).unwrap();
+        assert_eq!(globbed.global.concurrency, 4);
+        assert!(globbed.features.contains_key("c"));
+    }
+
+    #[test]
+    fn selection_lists_move_features_between_files() {
+        let mut base: Table = "[selection]\nstrict = [\"a\", \"b\"]\nextra = [\"c\"]\n"
+            .parse()
+            .unwrap();
+        let overlay: Table = "[selection]\nextra = [\"b\"]\n".parse().unwrap();
+
+        merge(&mut base, overlay);
+
+        let selection: Selection = base["selection"].clone().try_into().unwrap();
+        assert_eq!(selection.strict, vec!["a"]);
+        assert_eq!(selection.extra, vec!["c", "b"]);
+    }
+
+    #[test]
+    fn wildcards_match_file_names() {
+        let matches = |pattern: &str, name: &str| {
+            let chars = |s: &str| s.chars().collect::<Vec<_>>();
+            wildcard_match(&chars(pattern), &chars(name))
+        };
+
+        assert!(matches("*.toml", "ci.toml"));
+        assert!(matches("c?.toml", "ci.toml"));
+        assert!(!matches("*.toml", "ci.toml.bak"));
+        assert!(!matches("?.toml", ".toml"));
+    }
+
+    #[test]
+    fn missing_file_is_reported_as_not_found() {
+        let err = Config::new("/nonexistent/Configs.toml").err().unwrap();
+
+        assert!(
+            matches!(&err, MultiCheckError::ConfigNotFound(path) if path.ends_with("Configs.toml"))
+        );
+    }
+
+    #[test]
+    fn selection_lists_features_as_arrays() {
+        let global = "[global]\nclean = false\nclear_terminal = false\n";
+        let config = load(&format!(
+            "{}[selection]\nstrict = [\"a\", \"b\"]\nextra = [\"c\"]\n",
+            global
+        ))
+        .unwrap();
+        let both = load(&format!(
+            "{}[selection]\nstrict = [\"a\"]\n[features]\nb = {{ strict = false }}\n",
+            global
+        ));
+        let overlapping = load(&format!(
+            "{}[selection]\nstrict = [\"a\"]\nextra = [\"a\"]\n",
+            global
+        ));
+
+        let selection = config.selection.unwrap();
+        assert_eq!(selection.strict, vec!["a", "b"]);
+        assert_eq!(selection.extra, vec!["c"]);
+        assert!(config.features.is_empty());
+        assert!(matches!(both, Err(MultiCheckError::InvalidConfig(_))));
+        assert!(matches!(
+            overlapping,
+            Err(MultiCheckError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn ignored_combinations_match_in_any_order() {
+        let config = load(
+            "[global]\nclean = false\nclear_terminal = false\nignore = [[\"a\", \"b\"], [\"c\"]]\n[features]\n",
+        )
+        .unwrap();
+        let ignored = |combo: &[&str]| {
+            let combo: Vec<String> = combo.iter().map(|f| f.to_string()).collect();
+            config.global.is_ignored(&combo)
+        };
+
+        assert!(ignored(&["b", "a"]));
+        assert!(ignored(&["c"]));
+        assert!(!ignored(&["a"]));
+        assert!(!ignored(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn invalid_progress_template_is_rejected() {
+        let valid = load(
+            "[global]\nclean = false\nclear_terminal = false\nprogress_template = \"{pos}/{len}\"\n[features]\n",
+        );
+        let invalid = load(
+            "[global]\nclean = false\nclear_terminal = false\nspinner_template = \"{msg:abc}\"\n[features]\n",
+        );
+
+        assert_eq!(
+            valid.unwrap().global.progress_template.as_deref(),
+            Some("{pos}/{len}")
+        );
+        let err = invalid.err().unwrap();
+        assert!(matches!(err, MultiCheckError::InvalidConfig(_)));
+        assert!(
+            err.to_string().contains("invalid spinner_template"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn misspelled_key_is_reported_with_its_location() {
+        let err = match load(
+            "[global]\nconcurrancy = 2\nclean = false\nclear_terminal = false\n[features]\n",
+        ) {
+            Ok(_) => panic!("misspelled key was accepted"),
+            Err(err) => err,
+        };
+
+        assert!(matches!(err, MultiCheckError::InvalidConfig(_)));
+        let message = err.to_string();
+        assert!(message.contains("line 2, column 1"), "{}", message);
+        assert!(
+            message.contains("unknown field `concurrancy`"),
+            "{}",
+            message
+        );
+    }
 }
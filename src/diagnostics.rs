@@ -0,0 +1,188 @@
+use cargo_metadata::diagnostic::DiagnosticLevel;
+use cargo_metadata::Message;
+use console::Style;
+use serde::{Deserialize, Serialize};
+
+/// Argument making cargo report compiler messages as JSON on stdout.
+pub const MESSAGE_FORMAT_ARG: &str = "--message-format=json";
+
+/// Severity of a [`Diagnostic`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+/// A compiler error or warning of a check.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    /// Error code, e.g. `E0425` or `unused_imports`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// `file:line:column` of the primary span.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// The message as rustc prints it.
+    pub rendered: String,
+}
+
+impl Diagnostic {
+    /// Returns the first line of the message with its level and code, e.g.
+    /// `error[E0425]: cannot find value`, styled when `color` is set.
+    pub fn headline(&self, color: bool) -> String {
+        let level = match (self.level, &self.code) {
+            (Level::Error, Some(code)) => format!("error[{}]", code),
+            (Level::Error, None) => "error".to_string(),
+            (Level::Warning, Some(code)) => format!("warning[{}]", code),
+            (Level::Warning, None) => "warning".to_string(),
+        };
+        let style = level_style(self.level).force_styling(color);
+        format!("{}: {}", style.apply_to(level), self.message)
+    }
+}
+
+fn level_style(level: Level) -> Style {
+    match level {
+        Level::Error => Style::new().red().bold(),
+        Level::Warning => Style::new().yellow().bold(),
+    }
+}
+
+/// Splits the stdout of a cargo command run with [`MESSAGE_FORMAT_ARG`] into
+/// the compiler errors and warnings, deduplicated, and the remaining text.
+pub fn parse_messages(stdout: &str) -> (Vec<Diagnostic>, String) {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut text = String::new();
+    for message in Message::parse_stream(stdout.as_bytes()).flatten() {
+        match message {
+            Message::CompilerMessage(message) => {
+                let message = message.message;
+                let level = match message.level {
+                    DiagnosticLevel::Error | DiagnosticLevel::Ice => Level::Error,
+                    DiagnosticLevel::Warning => Level::Warning,
+                    _ => continue,
+                };
+                // Totals such as "aborting due to 2 previous errors".
+                if message.spans.is_empty() && message.code.is_none() && message.children.is_empty()
+                {
+                    continue;
+                }
+                let diagnostic = Diagnostic {
+                    level,
+                    location: message
+                        .spans
+                        .iter()
+                        .find(|span| span.is_primary)
+                        .map(|span| {
+                            format!(
+                                "{}:{}:{}",
+                                span.file_name, span.line_start, span.column_start
+                            )
+                        }),
+                    code: message.code.map(|code| code.code),
+                    rendered: message.rendered.unwrap_or_else(|| message.message.clone()),
+                    message: message.message,
+                };
+                // Targets sharing a source file report its diagnostics once each.
+                if !diagnostics.contains(&diagnostic) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+            Message::TextLine(line) => {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            _ => {}
+        }
+    }
+    (diagnostics, text)
+}
+
+/// Rebuilds the stderr of a cargo command from its own `stderr` and the
+/// `diagnostics` reported as JSON, in the order cargo prints them: before
+/// its closing `error: could not compile` and `warning: ... generated` lines.
+pub fn render_stderr(stderr: &str, diagnostics: &[Diagnostic]) -> String {
+    if diagnostics.is_empty() {
+        return stderr.to_string();
+    }
+    let rendered: String = diagnostics.iter().map(|d| d.rendered.as_str()).collect();
+    let pos = stderr
+        .match_indices('\n')
+        .map(|(i, _)| i + 1)
+        .chain([0])
+        .filter(|&i| {
+            let line = &stderr[i..];
+            line.starts_with("error") || line.starts_with("warning")
+        })
+        .min()
+        .unwrap_or(stderr.len());
+    format!("{}{}{}", &stderr[..pos], rendered, &stderr[pos..])
+}
+
+/// Counts the errors and warnings of `diagnostics`, e.g. `3 errors, 1 warning`,
+/// styled when `color` is set.
+pub fn summary(diagnostics: &[Diagnostic], color: bool) -> String {
+    let count = |level| diagnostics.iter().filter(|d| d.level == level).count();
+    [(Level::Error, "error"), (Level::Warning, "warning")]
+        .into_iter()
+        .filter_map(|(level, name)| {
+            let count = count(level);
+            let plural = if count == 1 { "" } else { "s" };
+            (count > 0).then(|| {
+                let style = level_style(level).force_styling(color);
+                style
+                    .apply_to(format!("{} {}{}", count, name, plural))
+                    .to_string()
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STDOUT: &str = r#"{"reason":"compiler-message","package_id":"demo 0.1.0 (path+file:///demo)","manifest_path":"/demo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"demo","src_path":"/demo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error[E0425]: cannot find value `x`\n","$message_type":"diagnostic","children":[],"code":{"code":"E0425","explanation":null},"level":"error","message":"cannot find value `x` in this scope","spans":[{"byte_end":20,"byte_start":19,"column_end":6,"column_start":5,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":"not found in this scope","line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}
+{"reason":"compiler-message","package_id":"demo 0.1.0 (path+file:///demo)","manifest_path":"/demo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"demo","src_path":"/demo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error: aborting due to 1 previous error\n","$message_type":"diagnostic","children":[],"code":null,"level":"error","message":"aborting due to 1 previous error","spans":[]}}
+plain output
+{"reason":"build-finished","success":false}
+"#;
+
+    #[test]
+    fn compiler_messages_are_parsed_apart_from_text() {
+        let (diagnostics, text) = parse_messages(STDOUT);
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                level: Level::Error,
+                message: "cannot find value `x` in this scope".to_string(),
+                code: Some("E0425".to_string()),
+                location: Some("src/lib.rs:2:5".to_string()),
+                rendered: "error[E0425]: cannot find value `x`\n".to_string(),
+            }]
+        );
+        assert_eq!(text, "plain output\n");
+        assert_eq!(
+            diagnostics[0].headline(false),
+            "error[E0425]: cannot find value `x` in this scope"
+        );
+        assert_eq!(summary(&diagnostics, false), "1 error");
+    }
+
+    #[test]
+    fn diagnostics_are_rendered_before_cargo_summary() {
+        let (diagnostics, _) = parse_messages(STDOUT);
+        let stderr = "    Checking demo\nerror: could not compile `demo`\n";
+
+        assert_eq!(
+            render_stderr(stderr, &diagnostics),
+            "    Checking demo\nerror[E0425]: cannot find value `x`\nerror: could not compile `demo`\n"
+        );
+        assert_eq!(render_stderr(stderr, &[]), stderr);
+    }
+}
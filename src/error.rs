@@ -0,0 +1,37 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors of loading a configuration, reading a project or its cache.
+#[derive(Debug, Error)]
+pub enum MultiCheckError {
+    #[error("configuration file {0:?} not found")]
+    ConfigNotFound(PathBuf),
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+    #[error("invalid manifest {path:?}: {message}")]
+    ManifestParse { path: PathBuf, message: String },
+    /// Features of the configuration that the manifest doesn't declare.
+    #[error("{} not declared in {manifest:?}: {}", if .features.len() == 1 { "feature is" } else { "features are" }, .features.join(", "))]
+    UnknownFeatures {
+        manifest: PathBuf,
+        features: Vec<String>,
+    },
+    #[error("{manifest:?} is a workspace, pass --package with one of its members: {members}")]
+    VirtualManifest { manifest: PathBuf, members: String },
+    #[error("package {package} is not a member of the workspace, members are: {members}")]
+    PackageNotFound { package: String, members: String },
+    #[error("cargo metadata failed: {0}")]
+    Metadata(#[from] cargo_metadata::Error),
+    #[error("invalid cache: {0}")]
+    CacheCorrupt(String),
+    #[error("git diff {git_ref} failed: {message}")]
+    Git { git_ref: String, message: String },
+    /// The warm-up cargo command failed, with its stderr.
+    #[error("{0}")]
+    Warmup(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, MultiCheckError>;
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::{fs, io};
+
+/// Returns whether combinations are stored in `path` as JSON rather than as
+/// one combination per line, which is decided by the `.json` extension.
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Writes `combinations` to `path`, returning the number written.
+///
+/// `.json` files hold an array of feature arrays, other files hold one
+/// combination per line with its features separated by spaces, the default
+/// features being an empty line.
+pub fn export_combinations(
+    path: &Path,
+    combinations: impl IntoIterator<Item = Vec<String>>,
+) -> io::Result<u64> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let json = is_json(path);
+    let mut count = 0;
+
+    if json {
+        write!(file, "[")?;
+    }
+    for combo in combinations {
+        if json {
+            if count > 0 {
+                write!(file, ",")?;
+            }
+            write!(file, "\n  {}", serde_json::to_string(&combo)?)?;
+        } else {
+            if let Some(feature) = combo
+                .iter()
+                .find(|f| f.is_empty() || f.contains(char::is_whitespace))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "feature {:?} can't be written one combination per line, use a .json file",
+                        feature
+                    ),
+                ));
+            }
+            writeln!(file, "{}", combo.join(" "))?;
+        }
+        count += 1;
+    }
+    if json {
+        writeln!(file, "\n]")?;
+    }
+    file.flush()?;
+    Ok(count)
+}
+
+/// Reads the combinations written by [`export_combinations`] to `path`.
+pub fn import_combinations(path: &Path) -> io::Result<Vec<Vec<String>>> {
+    if is_json(path) {
+        let contents = fs::read_to_string(path)?;
+        return serde_json::from_str(&contents)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+    }
+
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|line| Ok(line?.split_whitespace().map(str::to_string).collect()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::strings;
+    use crate::ALL_FEATURES;
+
+    fn combinations() -> Vec<Vec<String>> {
+        vec![
+            strings(&["a"]),
+            strings(&["a", "b-c"]),
+            vec![],
+            strings(&[ALL_FEATURES]),
+        ]
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["combos.txt", "combos.json"] {
+            let path = dir.path().join(name);
+
+            let written = export_combinations(&path, combinations()).unwrap();
+
+            assert_eq!(written, 4);
+            assert_eq!(
+                import_combinations(&path).unwrap(),
+                combinations(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn import_rejects_invalid_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("combos.json");
+        fs::write(&path, "[[\"a\"], \"b\"]").unwrap();
+
+        let err = import_combinations(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,48 @@
+//! Runs cargo against many feature combinations of a project.
+//!
+//! [`RustProject`] reads the features to test from a [`Config`] and the
+//! project's `Cargo.toml`, [`generate_combinations`] builds the combinations to
+//! check and [`run`] checks all of them.
+
+pub mod cache;
+pub mod changes;
+mod combinations;
+pub mod config;
+pub mod diagnostics;
+pub mod error;
+pub mod export;
+pub mod logs;
+mod pairwise;
+pub mod progress;
+mod project;
+pub mod report;
+mod runner;
+#[cfg(test)]
+mod test_support;
+
+pub use crate::combinations::{
+    count_combinations, explain, filter_combinations, generate_combinations, is_selected,
+    planned_combinations, special_combinations, uncovered_features, ALL_FEATURES,
+    NO_DEFAULT_FEATURES,
+};
+pub use crate::error::MultiCheckError;
+pub use crate::project::{manifest_features, package_manifest, RustProject};
+pub use crate::report::{CheckError, RunReport};
+pub use crate::runner::{
+    check_args, clear_project, format_command, minimize_combination, run_cargo_build,
+    run_combinations,
+};
+
+use crate::config::Config;
+
+/// Generates every combination of `project` and checks them with the settings
+/// of `config`.
+pub async fn run(config: &Config, project: &RustProject) -> RunReport {
+    let combinations = || {
+        filter_combinations(&config.global, generate_combinations(project))
+            .chain(special_combinations(&config.global))
+    };
+    // Generating twice is cheaper than holding every combination in memory.
+    let total = combinations().count() as u64;
+    run_combinations(&config.global, project, combinations(), total).await
+}
@@ -0,0 +1,145 @@
+use crate::combinations::{ALL_FEATURES, NO_DEFAULT_FEATURES};
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Longest log file name written, most file systems cap names at 255 bytes.
+const MAX_FILE_NAME_LEN: usize = 200;
+
+/// Creates a new directory for the logs of this run in `base`, named after
+/// the current UTC time, e.g. `2024-05-01T12-30-00`.
+pub fn create_run_dir(base: &Path) -> io::Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let name = timestamp(secs);
+    fs::create_dir_all(base)?;
+    // Runs started within the same second get a suffix.
+    let mut dir = base.join(&name);
+    let mut suffix = 1;
+    loop {
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                suffix += 1;
+                dir = base.join(format!("{}-{}", name, suffix));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Formats `secs` since the Unix epoch as a UTC date and time usable in file names.
+fn timestamp(secs: u64) -> String {
+    let (days, time) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Returns the name of the log file of `combo`: its features joined with `+`,
+/// characters unsafe in file names replaced with `_`.
+pub fn log_file_name(combo: &[String]) -> String {
+    let name = if combo.is_empty() {
+        "default-features".to_string()
+    } else if combo == [ALL_FEATURES] {
+        "all-features".to_string()
+    } else if combo == [NO_DEFAULT_FEATURES] {
+        "no-default-features".to_string()
+    } else {
+        combo.join("+")
+    };
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.len() > MAX_FILE_NAME_LEN {
+        // Keep long names unique with a hash of the whole combination.
+        let mut hasher = DefaultHasher::new();
+        combo.hash(&mut hasher);
+        name.truncate(MAX_FILE_NAME_LEN - 17);
+        name = format!("{}-{:016x}", name, hasher.finish());
+    }
+    name + ".log"
+}
+
+/// Appends the log of a check of `combo` to its file in `dir`: the command,
+/// whether it passed and the captured stdout and stderr. Every command run on
+/// a combination thus ends up in the same file.
+pub fn write_log(
+    dir: &Path,
+    combo: &[String],
+    command: &str,
+    passed: bool,
+    elapsed: Duration,
+    stdout: &str,
+    stderr: &str,
+) -> io::Result<()> {
+    let mut log = String::new();
+    let status = if passed { "passed" } else { "failed" };
+    let _ = writeln!(log, "$ {}", command);
+    let _ = writeln!(log, "{} in {:.2?}", status, elapsed);
+    let _ = writeln!(log, "\n--- stdout ---\n{}", stdout);
+    let _ = writeln!(log, "--- stderr ---\n{}", stderr);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(log_file_name(combo)))?
+        .write_all(log.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::strings;
+
+    #[test]
+    fn timestamp_formats_utc_date_and_time() {
+        assert_eq!(timestamp(0), "1970-01-01T00-00-00");
+        assert_eq!(timestamp(951_827_696), "2000-02-29T12-34-56");
+    }
+
+    #[test]
+    fn log_file_names_are_safe() {
+        assert_eq!(log_file_name(&strings(&["a", "b-c"])), "a+b-c.log");
+        assert_eq!(
+            log_file_name(&strings(&["serde/std", "x y"])),
+            "serde_std+x_y.log"
+        );
+        assert_eq!(log_file_name(&[]), "default-features.log");
+        assert_eq!(log_file_name(&strings(&[ALL_FEATURES])), "all-features.log");
+
+        let many: Vec<String> = (0..100).map(|i| format!("feature{}", i)).collect();
+        let name = log_file_name(&many);
+        assert!(name.len() <= MAX_FILE_NAME_LEN + ".log".len());
+        let mut more = many.clone();
+        more.push("extra".to_string());
+        assert_ne!(name, log_file_name(&more));
+    }
+}
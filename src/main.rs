@@ -1,476 +1,1160 @@
-mod cache;
-mod config;
-
-use crate::cache::{read_cache, write_cache};
-use crate::config::{Config, GlobalConfig};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::io::{BufRead, BufReader};
+mod cli;
+mod watch;
+
+use crate::cli::Cli;
+use indicatif::ProgressBar;
+use multi_feature_check::cache::{
+    default_cache_path, failures_path, legacy_cache_path, migrate_cache, read_cache, sources_hash,
+    write_cache, CacheEntry, CacheHeader,
+};
+use multi_feature_check::changes::{affected_features, changed_files, stale_paths};
+use multi_feature_check::config::{self, Config, GlobalConfig, Warmup};
+use multi_feature_check::diagnostics::{self, Level};
+use multi_feature_check::export::{export_combinations, import_combinations};
+use multi_feature_check::logs::create_run_dir;
+use multi_feature_check::progress::{
+    bar_style, finish_with_message, interactive, status_spinner, ColorChoice,
+};
+use multi_feature_check::report::{
+    group_by_message, sarif_uri, CheckResult, CheckStatus, OutputFormat, Report,
+};
+use multi_feature_check::{
+    check_args, clear_project, explain, filter_combinations, format_command, generate_combinations,
+    is_selected, manifest_features, minimize_combination, package_manifest, planned_combinations,
+    run_cargo_build, run_combinations, special_combinations, uncovered_features, CheckError,
+    MultiCheckError, RunReport, RustProject, ALL_FEATURES, NO_DEFAULT_FEATURES,
+};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process::{exit, Command, ExitStatus, Stdio};
-use std::time::Duration;
-use std::{env, io};
-
-#[derive(Debug)]
-struct CheckError {
-    combination: Vec<String>,
-    message: String,
-}
+use std::process::exit;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
 
-#[derive(Clone)]
-struct RustProject {
-    hash: u64,
-    configs: GlobalConfig,
-    path: PathBuf,
-    features: Vec<String>,
-    extra_features: Vec<String>,
-    dependencies: HashMap<String, Vec<String>>,
+/// Prints like `println!`, unless `--quiet` is set in `configs`.
+macro_rules! info {
+    ($configs:expr, $($arg:tt)*) => {
+        if !$configs.quiet {
+            println!($($arg)*);
+        }
+    };
 }
 
-impl RustProject {
-    fn new(path: &str, configs: &str, cargo: Option<&String>) -> io::Result<Self> {
-        let full_path = Path::new(path).canonicalize()?;
-        let cargo_toml = match cargo {
-            Some(c) => Path::new(c).canonicalize()?,
-            None => full_path.join("Cargo.toml"),
-        };
-        let configs = Config::new(configs)?;
-        let global_config = configs.global.clone();
+/// Number of slowest combinations listed at the end of a run.
+const SLOWEST_COMBINATIONS: usize = 5;
 
-        let (features, extra) = categorize_features(configs);
+/// Exit code of a run interrupted with Ctrl-C, as shells report for SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
 
-        let all_features = features.iter().chain(extra.iter()).collect::<HashSet<_>>();
-
-        let dependencies = extract_dependencies(&cargo_toml, all_features)?;
-        let hash = hash_features(&features, &dependencies);
-        Ok(Self {
-            hash,
-            configs: global_config,
-            path: full_path,
-            features,
-            extra_features: extra,
-            dependencies,
-        })
+/// Streams the combinations of `project` into a new cache, returning its
+/// header and the number of combinations.
+///
+/// `total` is the planned number of combinations, an upper bound since
+/// implied features and exclusive groups drop some of them: the bar
+/// is given the actual count once generation finishes, so that it ends full.
+fn regenerate_cache(
+    project: &RustProject,
+    cache_file: &Path,
+    total: u64,
+) -> Result<(CacheHeader, u64), MultiCheckError> {
+    let pb = if interactive(&project.configs) {
+        ProgressBar::new(total)
+    } else {
+        ProgressBar::hidden()
+    };
+    pb.set_style(bar_style(
+        &project.configs,
+        "[{elapsed_precise}] {wide_bar:0.cyan/blue} Generated {pos}/{len} ({percent}%)",
+    ));
+    let header = CacheHeader {
+        hash: project.hash,
+        ..CacheHeader::default()
+    };
+    // The style is set once and indicatif rate-limits redraws, so per-combination `inc` is cheap:
+    // 196607 combinations generate and write in well under a second, not worth splitting
+    // across threads.
+    // Unfiltered, so runs with different filters share the cache and its results.
+    let entries = generate_combinations(project)
+        .inspect(|_| pb.inc(1))
+        .map(|combo| Ok((combo, None)));
+    let count = write_cache(cache_file, &header, entries);
+    if let Ok(count) = count {
+        pb.set_length(count);
     }
+    pb.finish_and_clear();
+    Ok((header, count?))
 }
 
-fn categorize_features(config: Config) -> (Vec<String>, Vec<String>) {
-    let mut main_features = Vec::new();
-    let mut extra_features = Vec::new();
+/// Reads the entries of `cache_file` kept by the `--only-feature`,
+/// `--skip-feature` and `--since` filters. The cache was validated when the
+/// run started, but another run may remove or truncate it meanwhile: reading
+/// then stops at the first error, left in `error` for [`cache_read_failed`].
+fn selected_entries<'a>(
+    configs: &'a GlobalConfig,
+    cache_file: &Path,
+    error: &'a Cell<Option<MultiCheckError>>,
+) -> impl Iterator<Item = CacheEntry> + 'a {
+    let entries = match read_cache(cache_file) {
+        Ok((_, entries)) => Some(entries),
+        Err(err) => {
+            error.set(Some(err));
+            None
+        }
+    };
+    entries
+        .into_iter()
+        .flatten()
+        .map_while(move |entry| entry.map_err(|err| error.set(Some(err))).ok())
+        .filter(move |(combo, _)| is_selected(configs, combo))
+}
 
-    for (feature, details) in config.features {
-        if details.strict {
-            main_features.push(feature);
-        } else {
-            extra_features.push(feature);
+/// Prints the error left by [`selected_entries`], if any, returning whether
+/// there was one.
+fn cache_read_failed(cache_file: &Path, error: &Cell<Option<MultiCheckError>>) -> bool {
+    match error.take() {
+        Some(err) => {
+            print_error(&format!("Failed to read cache {:?}", cache_file), &err);
+            true
         }
+        None => false,
+    }
+}
+
+/// Reads the header of `cache_file` and checks every entry can be read,
+/// returning the header and the number of entries.
+fn validate_cache(cache_file: &Path) -> Result<(CacheHeader, u64), MultiCheckError> {
+    let (header, entries) = read_cache(cache_file)?;
+    let mut count = 0;
+    for entry in entries {
+        entry?;
+        count += 1;
     }
+    Ok((header, count))
+}
 
-    main_features.sort();
-    extra_features.sort();
-    (main_features, extra_features)
+/// Clears the terminal with ANSI escape codes, does nothing when stdout is not a terminal.
+fn clear_terminal() {
+    let mut stdout = io::stdout();
+    if !stdout.is_terminal() {
+        return;
+    }
+
+    if write!(stdout, "\x1b[2J\x1b[H")
+        .and_then(|_| stdout.flush())
+        .is_err()
+    {
+        eprintln!("Failed to clear terminal");
+    }
 }
 
-fn extract_dependencies(
-    file_path: &PathBuf,
-    features: HashSet<&String>,
-) -> io::Result<HashMap<String, Vec<String>>> {
-    let mut dependencies = HashMap::new();
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
+/// Writes a starter configuration to `config_path` listing the features of `cargo_toml`.
+fn init_config(
+    cargo_toml: &Path,
+    config_path: &Path,
+    force: bool,
+) -> Result<usize, MultiCheckError> {
+    if config_path.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "configuration already exists, pass --force to overwrite it",
+        )
+        .into());
+    }
+    let features = manifest_features(cargo_toml)?;
+    std::fs::write(config_path, config::template(&features))?;
+    Ok(features.len())
+}
 
-    let mut in_features_section = false;
+/// Prints the diagnostic logs to stderr, filtered by `level` or else by
+/// `RUST_LOG`, warnings only by default.
+fn init_logging(level: Option<&str>, color: ColorChoice) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(color.enabled())
+        .with_target(false)
+        .without_time()
+        .init();
+}
 
-    for line in reader.lines() {
-        let line = line?;
-        if line.trim() == "[features]" {
-            in_features_section = true;
-            continue;
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse_args();
+    init_logging(cli.log_level.as_deref(), cli.color);
+    let project_dir = cli.project_dir();
+    let config_paths = cli.config_paths();
+    let config_path = config_paths.join(", ");
+    let cargo = cli.manifest().cloned();
+    let cargo = cargo.as_ref();
+
+    if cli.init {
+        if config_paths.len() > 1 {
+            eprintln!("--init writes a single configuration file");
+            exit(1);
+        }
+        let mut cargo_toml = match cargo {
+            Some(cargo) => PathBuf::from(cargo),
+            None => Path::new(&project_dir).join("Cargo.toml"),
+        };
+        if let Some(package) = &cli.package {
+            cargo_toml = package_manifest(&cargo_toml, package).unwrap_or_else(|err| {
+                print_error(&format!("Failed to find package {}", package), &err);
+                exit(1);
+            });
         }
-        if in_features_section {
-            if line.starts_with('[') {
-                break;
+        match init_config(&cargo_toml, Path::new(&config_path), cli.force) {
+            Ok(count) => println!("Wrote {:?} with {} features", config_path, count),
+            Err(err) => {
+                print_error(
+                    &format!("Failed to write configuration {:?}", config_path),
+                    &err,
+                );
+                exit(1);
             }
-            if let Some(pos) = line.find('=') {
-                let feature = line[..pos].trim().to_string();
-
-                // Check if feature is in list of features
-                if !features.contains(&feature) && feature != "default" {
-                    // Skip if feature is not in list of features and warn user
-                    eprintln!(
-                        "Warning: Feature {} is not in list of tested features",
-                        feature
-                    );
-                }
+        }
+        return;
+    }
 
-                let deps: Vec<String> = line[pos + 1..]
-                    .trim()
-                    .trim_start_matches('[')
-                    .trim_end_matches(']')
-                    .split(',')
-                    .filter_map(|s| {
-                        let trimmed = s.trim().trim_matches('"');
-                        // Remove "
-                        if trimmed.is_empty() || trimmed.starts_with("dep:") {
-                            None
-                        } else {
-                            Some(trimmed.to_string())
-                        }
-                    })
-                    .collect();
-                dependencies.insert(feature, deps);
+    let mut configs = Config::load(&config_paths).unwrap_or_else(|err| {
+        print_error(
+            &format!("Failed to load configuration {:?}", config_path),
+            &err,
+        );
+        exit(1);
+    });
+    tracing::debug!(
+        path = %config_path,
+        features = configs.features.len()
+            + configs
+                .selection
+                .as_ref()
+                .map_or(0, |selection| selection.strict.len() + selection.extra.len()),
+        "loaded configuration"
+    );
+    if cli.target.is_some() {
+        configs.global.target = cli.target.clone();
+    }
+    if cli.profile.is_some() {
+        configs.global.profile = cli.profile.clone();
+    }
+    if cli.toolchain.is_some() {
+        configs.global.toolchain = cli.toolchain.clone();
+    }
+    if cli.verbose {
+        configs.global.verbose = true;
+    }
+    if cli.minimize {
+        configs.global.minimize = true;
+    }
+    if cli.exit_zero {
+        configs.global.allow_failures = true;
+    }
+    if let Some(budget) = cli.minimize_budget {
+        configs.global.minimize_budget = budget;
+    }
+    if let Some(strategy) = cli.strategy {
+        configs.global.strategy = strategy;
+    }
+    configs.global.color = cli.color;
+    configs.global.no_progress = cli.no_progress;
+    // Nothing else may be printed to stdout before a TAP, SARIF or JSON report.
+    configs.global.quiet = cli.quiet
+        || matches!(cli.output, OutputFormat::Tap | OutputFormat::Sarif)
+        || (cli.output == OutputFormat::Json && cli.report_file.is_none());
+    configs.global.package = cli.package.clone();
+    configs.global.only_features = cli.only_features.clone();
+    configs.global.skip_features = cli.skip_features.clone();
+    if configs.global.verbose {
+        // Interleaved output from parallel checks would be unreadable.
+        configs.global.concurrency = 1;
+    }
+    if let Err(err) = configs.validate() {
+        print_error(
+            &format!("Failed to load configuration {:?}", config_path),
+            &err,
+        );
+        exit(1);
+    }
+    for (feature, path) in stale_paths(Path::new(&project_dir), &configs.paths) {
+        warn!("Path {:?} of feature {} doesn't exist", path, feature);
+    }
+    set_changed_features(&cli, &project_dir, &mut configs);
+    if cli.count {
+        let count = RustProject::new(&project_dir, &configs, cargo)
+            .and_then(|project| count_combinations_to_check(&cli, &project));
+        match count {
+            Ok(count) => println!("{}", count),
+            Err(err) => {
+                print_error(
+                    &format!("Failed to count combinations of {:?}", project_dir),
+                    &err,
+                );
+                exit(1);
             }
         }
+        return;
     }
+    loop {
+        if let Some(base) = cli
+            .log_dir
+            .as_ref()
+            .filter(|_| !cli.dry_run && cli.export_combos.is_none())
+        {
+            match create_run_dir(base) {
+                Ok(dir) => configs.global.log_dir = Some(dir),
+                Err(err) => {
+                    eprintln!("Failed to create log directory in {:?}: {}", base, err);
+                    exit(1);
+                }
+            }
+        }
+        let code = match RustProject::new(&project_dir, &configs, cargo) {
+            Ok(project) => check(&cli, &project, &config_path).await,
+            Err(err) => {
+                print_error(&format!("Failed to read project {:?}", project_dir), &err);
+                1
+            }
+        };
+        if !cli.watch || code == INTERRUPTED_EXIT_CODE {
+            exit(code);
+        }
 
-    Ok(dependencies)
+        let manifest = match cargo {
+            Some(cargo) => PathBuf::from(cargo),
+            None => Path::new(&project_dir).join("Cargo.toml"),
+        };
+        let manifest = match &configs.global.package {
+            Some(package) => package_manifest(&manifest, package).unwrap_or(manifest),
+            None => manifest,
+        };
+        info!(
+            configs.global,
+            "\nWatching for changes, press Ctrl-C to exit"
+        );
+        if !watch::wait_for_changes(&manifest).await {
+            exit(INTERRUPTED_EXIT_CODE);
+        }
+        set_changed_features(&cli, &project_dir, &mut configs);
+        clear_terminal();
+    }
 }
 
-fn hash_features(features: &[String], dependencies: &HashMap<String, Vec<String>>) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    features.hash(&mut hasher);
-    for feature in features {
-        if let Some(deps) = dependencies.get(feature) {
-            deps.hash(&mut hasher);
+/// Prints `err` after `context`, followed by a hint on fixing it when there is one.
+fn print_error(context: &str, err: &MultiCheckError) {
+    eprintln!("{}: {}", context, err);
+    let hint = match err {
+        MultiCheckError::ConfigNotFound(_) => {
+            "Write one listing the features of the project with `cargo multi-check --init`."
+        }
+        MultiCheckError::InvalidConfig(_) => {
+            "Every setting is described in the Configuration section of the README."
+        }
+        MultiCheckError::ManifestParse { .. } | MultiCheckError::Metadata(_) => {
+            "Check that `cargo metadata` accepts the manifest."
+        }
+        MultiCheckError::UnknownFeatures { .. } => {
+            "Remove the undeclared features from the configuration, or write a new one with `--init --force`."
+        }
+        MultiCheckError::CacheCorrupt(_) => "Delete the cache file to generate it again.",
+        MultiCheckError::Git { .. } => {
+            "--since needs the project to be in a git repository where the reference exists."
+        }
+        MultiCheckError::Warmup(_) => {
+            "Use warmup = \"fetch\" when the features can't all be built together, or \"none\"."
+        }
+        MultiCheckError::VirtualManifest { .. }
+        | MultiCheckError::PackageNotFound { .. }
+        | MultiCheckError::Io(_) => return,
+    };
+    eprintln!("hint: {}", hint);
+}
+
+/// Restricts the combinations to the features affected by the changes since
+/// `--since`, exiting when they can't be listed.
+fn set_changed_features(cli: &Cli, project_dir: &str, configs: &mut Config) {
+    let Some(git_ref) = &cli.since else {
+        return;
+    };
+    let project_dir = Path::new(project_dir);
+    match changed_files(project_dir, git_ref) {
+        Ok(files) => {
+            configs.global.changed_features =
+                affected_features(project_dir, &files, &configs.paths);
+        }
+        Err(err) => {
+            print_error(
+                &format!("Failed to list the changes since {}", git_ref),
+                &err,
+            );
+            exit(1);
         }
     }
-    hasher.finish()
 }
 
-fn generate_combinations(project: &RustProject) -> Vec<Vec<String>> {
-    let n = project.features.len();
-    let pb = ProgressBar::new(((1 << n) * (project.extra_features.len() + 1)) as u64);
-    let style = ProgressStyle::default_bar()
-        .template("{bar:40.cyan/blue} {pos}/{len}")
-        .unwrap()
-        .progress_chars("#>-");
-    pb.set_style(style);
-    let mut combinations = Vec::new();
-    for i in 1..(1 << n) {
-        let mut combo = Vec::new();
-        let mut include = HashSet::new();
-        let mut exclude = HashSet::new();
-        for j in 0..n {
-            if i & (1 << j) != 0 {
-                let feature = &project.features[j];
-                if !exclude.contains(feature) {
-                    combo.push(feature.clone());
-                    include.insert(feature.clone());
-                    if let Some(deps) = project.dependencies.get(feature) {
-                        for dep in deps {
-                            exclude.insert(dep.clone());
-                        }
-                    }
-                }
-            }
+/// Returns whether `--since` found no changed feature among the tested ones.
+fn no_changed_feature(project: &RustProject) -> bool {
+    project
+        .configs
+        .changed_features
+        .as_ref()
+        .is_some_and(Vec::is_empty)
+}
+
+/// Returns the number of combinations a run would check, generating them
+/// without the cache.
+fn count_combinations_to_check(cli: &Cli, project: &RustProject) -> Result<u64, MultiCheckError> {
+    if let Some(path) = &cli.import_combos {
+        return Ok(import_combinations(path)?.len() as u64);
+    }
+    if no_changed_feature(project) {
+        return Ok(0);
+    }
+    let generated = filter_combinations(&project.configs, generate_combinations(project)).count();
+    Ok((generated + special_combinations(&project.configs).len()) as u64)
+}
+
+/// Checks the combinations of `project` and reports the results, returning
+/// the exit code of the run.
+async fn check(cli: &Cli, project: &RustProject, config_path: &str) -> i32 {
+    let timer = Instant::now();
+    if let Some(features) = &cli.explain {
+        let combo: Vec<String> = features
+            .split([',', ' '])
+            .filter(|feature| !feature.is_empty())
+            .map(str::to_string)
+            .collect();
+        for line in explain(project, &combo) {
+            println!("{}", line);
         }
-        let filtered_combo: Vec<String> =
-            combo.into_iter().filter(|f| !exclude.contains(f)).collect();
-        if !filtered_combo.is_empty() {
-            for extra in &project.extra_features {
-                let mut extended_combo = filtered_combo.clone();
-                extended_combo.push(extra.clone());
-                combinations.push(extended_combo);
-                pb.inc(1);
-            }
-            combinations.push(filtered_combo);
+        return 0;
+    }
+    let cache_file = cli.cache_file.clone().unwrap_or_else(|| {
+        let cache_file = default_cache_path(&project.target_dir);
+        match migrate_cache(&legacy_cache_path(&project.path), &cache_file) {
+            Ok(true) => info!(project.configs, "Moved the cache to {:?}", cache_file),
+            Ok(false) => {}
+            Err(err) => warn!("Failed to move the cache to {:?}: {}", cache_file, err),
+        }
+        cache_file
+    });
 
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{bar:40.green} {pos}/{len}")
-                    .unwrap(),
+    if project.configs.clear_terminal {
+        clear_terminal();
+    }
+
+    info!(project.configs, "Testing project: {:?}", project.path);
+    if let Some(package) = &project.configs.package {
+        info!(project.configs, "Testing package: {}", package);
+    }
+    info!(
+        project.configs,
+        "Using configuration file: {:?}", config_path
+    );
+    info!(
+        project.configs,
+        "Setting concurrency to: {}, {} cargo jobs each",
+        project.configs.concurrency,
+        project.configs.cargo_jobs()
+    );
+    info!(
+        project.configs,
+        "Using command: {}",
+        project
+            .configs
+            .commands()
+            .into_iter()
+            .map(|command| format_command(&check_args(&project.configs, command, &[])))
+            .collect::<Vec<_>>()
+            .join(", then ")
+    );
+    if let Some(dir) = &project.configs.log_dir {
+        info!(project.configs, "Writing logs to: {:?}", dir);
+    }
+    if let Some(target) = &project.configs.target {
+        info!(project.configs, "Using target: {}", target);
+    }
+    info!(
+        project.configs,
+        "--------------------------------------------------\n\n"
+    );
+    info!(project.configs, "Found features: {:?}", project.features);
+    info!(
+        project.configs,
+        "Found extra features: {:?}", project.extra_features
+    );
+    for feature in project
+        .configs
+        .only_features
+        .iter()
+        .chain(&project.configs.skip_features)
+    {
+        if !project.features.contains(feature) && !project.extra_features.contains(feature) {
+            warn!("Filtered feature {} is not a tested feature", feature);
+        }
+    }
+    for feature in project.configs.ignore.iter().flatten() {
+        if !project.features.contains(feature)
+            && !project.extra_features.contains(feature)
+            && feature != ALL_FEATURES
+            && feature != NO_DEFAULT_FEATURES
+        {
+            warn!("Ignored feature {} is not a tested feature", feature);
+        }
+    }
+    for feature in project.configs.exclusive.iter().flatten() {
+        if !project.features.contains(feature) && !project.extra_features.contains(feature) {
+            warn!("Exclusive feature {} is not a tested feature", feature);
+        }
+    }
+    if !project.configs.only_features.is_empty() {
+        info!(
+            project.configs,
+            "Only combinations with: {:?}", project.configs.only_features
+        );
+    }
+    if !project.configs.skip_features.is_empty() {
+        info!(
+            project.configs,
+            "Skipping combinations with: {:?}", project.configs.skip_features
+        );
+    }
+    if let (Some(git_ref), Some(changed)) = (&cli.since, &project.configs.changed_features) {
+        if no_changed_feature(project) && !cli.dry_run && cli.export_combos.is_none() {
+            info!(
+                project.configs,
+                "No tested feature changed since {}, nothing to check", git_ref
+            );
+            return summarize(cli, project, RunReport::default(), timer);
+        }
+        info!(
+            project.configs,
+            "Features changed since {}: {:?}", git_ref, changed
+        );
+    }
+    for (feature, dependencies) in &project.dependencies {
+        if (dependencies.is_empty()) || dependencies == &[""] {
+            continue;
+        }
+        info!(
+            project.configs,
+            "Feature: {} depends on {:?}", feature, dependencies
+        );
+    }
+
+    let import_path = match &cli.import_combos {
+        Some(path) => Some(path.clone()),
+        None if cli.rerun_failed => Some(failures_path(&cache_file)),
+        None => None,
+    };
+    if let Some(path) = import_path {
+        let combinations = match import_combinations(&path) {
+            Ok(combinations) => combinations,
+            Err(err) if cli.rerun_failed && err.kind() == io::ErrorKind::NotFound => {
+                info!(
+                    project.configs,
+                    "No combination failed in the last run, nothing to rerun"
+                );
+                return 0;
+            }
+            Err(err) => {
+                eprintln!("Failed to import combinations from {:?}: {}", path, err);
+                return 1;
+            }
+        };
+        if cli.rerun_failed {
+            info!(
+                project.configs,
+                "Rerunning {} combinations that failed in the last run",
+                combinations.len()
             );
         } else {
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{bar:40.red} {pos}/{len}")
-                    .unwrap(),
+            info!(
+                project.configs,
+                "Imported {} combinations from {:?}",
+                combinations.len(),
+                path
             );
         }
-
-        pb.inc(1);
+        let total = combinations.len() as u64;
+        if cli.dry_run {
+            print_combinations(combinations, total);
+            return 0;
+        }
+        if !prepare(project, &cache_file).await {
+            return 1;
+        }
+        let run_report = execute(project, combinations, total).await;
+        // Imported combinations are a subset of the matrix, unlike the
+        // failures being rerun.
+        let complete = cli.rerun_failed && !run_report.stopped_early;
+        record_failures(&cache_file, &run_report, complete);
+        return summarize(cli, project, run_report, timer);
     }
-    pb.finish();
 
-    // Add extra features only
-    for extra in &project.extra_features {
-        combinations.push(vec![extra.clone()]);
+    // Calculer et afficher le nombre total de combinaisons
+    let total_combinations = planned_combinations(project);
+    info!(
+        project.configs,
+        "Total possible combinations: {}", total_combinations
+    );
+    if total_combinations > project.configs.max_combinations
+        && !cli.yes
+        && !project.configs.allow_many_combinations
+    {
+        eprintln!(
+            "{} combinations is more than max_combinations ({}), this run could take days.",
+            total_combinations, project.configs.max_combinations
+        );
+        eprintln!(
+            "Use strategy = \"pairwise\" or max_combination_size to check fewer combinations, or pass --yes to run them all."
+        );
+        return 1;
     }
-    combinations
-}
 
-async fn make_checks(
-    combo: Vec<String>,
-    path: &Path,
-    check_pb: &ProgressBar,
-    global_pb: &ProgressBar,
-) -> Result<ExitStatus, (String, Vec<String>)> {
-    let combo_str = combo.join(" ");
-
-    let output = {
-        if combo_str.is_empty() {
-            check_pb.set_message("Running cargo check");
-            Command::new("cargo")
-                .current_dir(path)
-                .arg("check")
-                .stderr(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
-                .unwrap()
-                .wait_with_output()
-                .unwrap()
-        } else {
-            check_pb.set_message(format!(
-                "Running cargo check --no-default-features --features \"{}\"",
-                combo_str
-            ));
-            Command::new("cargo")
-                .current_dir(path)
-                .arg("check")
-                .arg("--no-default-features")
-                .arg("--features")
-                .arg(&combo_str)
-                .stderr(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
-                .unwrap()
-                .wait_with_output()
-                .unwrap()
+    info!(project.configs, "Using cache file: {:?}", cache_file);
+    let cache = match validate_cache(&cache_file) {
+        Ok((header, count)) if header.hash == project.hash => {
+            tracing::info!(?cache_file, combinations = count, "cache hit");
+            info!(project.configs, "Using cached combinations");
+            Ok((header, count))
+        }
+        Ok(_) => {
+            tracing::info!(?cache_file, "cache miss, features have changed");
+            info!(
+                project.configs,
+                "Features have changed, regenerating combinations"
+            );
+            regenerate_cache(project, &cache_file, total_combinations)
+        }
+        Err(MultiCheckError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+            tracing::info!(?cache_file, "cache miss, no cache");
+            info!(project.configs, "No cache found, generating combinations");
+            regenerate_cache(project, &cache_file, total_combinations)
+        }
+        Err(err) => {
+            tracing::info!(?cache_file, %err, "cache miss, invalid cache");
+            info!(
+                project.configs,
+                "Invalid cache ({}), regenerating combinations", err
+            );
+            regenerate_cache(project, &cache_file, total_combinations)
+        }
+    };
+    let (header, cached_count) = match cache {
+        Ok(cache) => cache,
+        Err(err) => {
+            print_error(&format!("Failed to write cache {:?}", cache_file), &err);
+            return 1;
         }
     };
 
-    if output.status.success() {
-        global_pb.inc(1);
-        Ok(output.status)
+    let cache_error = Cell::new(None);
+    let selected = || selected_entries(&project.configs, &cache_file, &cache_error);
+
+    // Combinations narrowed down on purpose leave features out as expected.
+    let narrowed =
+        !project.configs.only_features.is_empty() || project.configs.changed_features.is_some();
+    let uncovered = if narrowed {
+        Vec::new()
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        global_pb.inc(1);
-        Err((stderr, combo))
+        uncovered_features(project, selected().map(|(combo, _)| combo))
+    };
+    for feature in &uncovered {
+        warn!(
+            "Feature {} is in none of the combinations, check its exclusive groups, requires and max_combination_size",
+            feature
+        );
     }
-}
 
-async fn run_cargo_build(project_dir: &Path, pb: &ProgressBar) -> io::Result<()> {
-    pb.set_message("Fetching dependencies");
-    let output = Command::new("cargo")
-        .arg("build")
-        .arg("--all-features")
-        .current_dir(project_dir)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .unwrap()
-        .wait_with_output()
-        .unwrap();
-
-    if output.status.success() {
-        Ok(())
+    let specials = special_combinations(&project.configs);
+    let filtered = !project.configs.skip_features.is_empty() || narrowed;
+    let selected_count = if filtered {
+        selected().count() as u64
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(io::Error::new(io::ErrorKind::Other, stderr))
+        cached_count
+    };
+    if cache_read_failed(&cache_file, &cache_error) {
+        return 1;
     }
-}
+    let unique_combinations = selected_count + specials.len() as u64;
+    info!(
+        project.configs,
+        "Total unique combinations: {}", unique_combinations
+    );
 
-async fn clear_terminal() {
-    let status = Command::new("clear").status().unwrap();
-
-    if !status.success() {
-        eprintln!("Failed to clear terminal");
+    if let Some(path) = &cli.export_combos {
+        let combinations = selected().map(|(combo, _)| combo).chain(specials);
+        let exported = export_combinations(path, combinations);
+        if cache_read_failed(&cache_file, &cache_error) {
+            return 1;
+        }
+        return match exported {
+            Ok(count) => {
+                info!(
+                    project.configs,
+                    "Exported {} combinations to {:?}", count, path
+                );
+                0
+            }
+            Err(err) => {
+                eprintln!("Failed to export combinations to {:?}: {}", path, err);
+                1
+            }
+        };
     }
-}
 
-async fn clear_project(project: &RustProject) -> Result<(), String> {
-    let status = Command::new("cargo")
-        .arg("clean")
-        .current_dir(&project.path)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .status()
-        .unwrap();
-
-    if !status.success() {
-        Err("Failed to clean project".to_string())
-    } else {
-        Ok(())
+    if cli.dry_run {
+        let combinations = selected().map(|(combo, _)| combo).chain(specials);
+        print_combinations(combinations, unique_combinations);
+        return if cache_read_failed(&cache_file, &cache_error) {
+            1
+        } else {
+            0
+        };
     }
-}
 
-#[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 3 {
-        println!("Please provide a rust project file path as an argument and a configuration file path as an argument");
-        return;
+    if !prepare(project, &cache_file).await {
+        return 1;
     }
 
-    if args[1] == "--help" {
-        println!(
-            "Usage: cargo run <path_to_cargo_toml> <path_to_toml_config> [cargo_toml_file_name]"
+    // After the warm-up, which may have created or updated Cargo.lock.
+    let sources = sources_hash(&project.path, &project.configs, &cache_file);
+    let use_results = !cli.no_cache && header.sources_hash == sources;
+    let known_passed =
+        |status: &Option<CheckStatus>| use_results && *status == Some(CheckStatus::Passed);
+    let known_passed_count = if use_results {
+        selected()
+            .filter(|(_, status)| known_passed(status))
+            .count() as u64
+    } else {
+        0
+    };
+    if cache_read_failed(&cache_file, &cache_error) {
+        return 1;
+    }
+    if known_passed_count > 0 {
+        info!(
+            project.configs,
+            "Skipping {} combinations that passed last time, pass --no-cache to check them again",
+            known_passed_count
         );
-        return;
+    }
+    let to_check = selected()
+        .filter(|(_, status)| !known_passed(status))
+        .map(|(combo, _)| combo)
+        .chain(specials);
+    let mut run_report = execute(project, to_check, unique_combinations - known_passed_count).await;
+    run_report.uncovered = uncovered;
+    if known_passed_count > 0 {
+        run_report.passed.extend(
+            selected()
+                .filter(|(_, status)| known_passed(status))
+                .map(|(combo, _)| CheckResult::cached(combo)),
+        );
+    }
+    // The results are incomplete, the cache is left as it was.
+    if cache_read_failed(&cache_file, &cache_error) {
+        return 1;
     }
 
-    let cargo = {
-        if args.len() > 3 {
-            Some(&args[3])
-        } else {
-            None
-        }
+    // Results from before the sources changed no longer hold.
+    let keep_old_results = header.sources_hash == sources;
+    let results: HashMap<&[String], CheckStatus> = run_report
+        .passed
+        .iter()
+        .map(|result| (result.combination.as_slice(), CheckStatus::Passed))
+        .chain(
+            run_report
+                .failed
+                .iter()
+                .chain(&run_report.ignored)
+                .chain(&run_report.invalid)
+                .map(|fail| (fail.combination.as_slice(), CheckStatus::Failed)),
+        )
+        .collect();
+    let header = CacheHeader {
+        hash: header.hash,
+        sources_hash: sources,
     };
+    // A read error fails the write, which keeps the previous cache.
+    let written = read_cache(&cache_file).and_then(|(_, entries)| {
+        let entries = entries.map(|entry| {
+            entry.map(|(combo, status)| {
+                let status = match results.get(combo.as_slice()) {
+                    Some(status) => Some(*status),
+                    None if keep_old_results => status,
+                    None => None,
+                };
+                (combo, status)
+            })
+        });
+        write_cache(&cache_file, &header, entries)
+    });
+    if let Err(err) = written {
+        print_error(&format!("Failed to update cache {:?}", cache_file), &err);
+    }
 
-    let timer = std::time::Instant::now();
-    let project =
-        RustProject::new(&args[1], &args[2], cargo).expect("Failed to create Rust project");
-    let cache_file = "feature_combinations.cache";
+    let complete = !filtered && !run_report.stopped_early;
+    record_failures(&cache_file, &run_report, complete);
+    summarize(cli, project, run_report, timer)
+}
 
-    if project.configs.clear_terminal {
-        clear_terminal().await;
+/// Saves the combinations that failed in `run_report` for `--rerun-failed`,
+/// removing the saved ones when none failed. Runs that didn't check every
+/// combination, `complete` being false, only drop the saved combinations
+/// they saw pass. Interrupted runs leave them as is.
+fn record_failures(cache_file: &Path, run_report: &RunReport, complete: bool) {
+    if run_report.interrupted {
+        return;
     }
-
-    println!("Testing project: {:?}", project.path);
-    println!("Using configuration file: {:?}", args[2]);
-    println!("Setting concurrency to: {}", project.configs.concurrency);
-    println!("--------------------------------------------------\n\n");
-    println!("Found features: {:?}", project.features);
-    println!("Found extra features: {:?}", project.extra_features);
-    for (feature, dependencies) in &project.dependencies {
-        if (dependencies.is_empty()) || dependencies == &[""] {
-            continue;
+    let path = failures_path(cache_file);
+    let saved = if complete {
+        Ok(Vec::new())
+    } else {
+        match import_combinations(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            saved => saved,
         }
-        println!("Feature: {} depends on {:?}", feature, dependencies);
+    };
+    let result = saved.and_then(|saved| {
+        let failed = run_report.still_failing(saved);
+        if failed.is_empty() {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            export_combinations(&path, failed).map(|_| ())
+        }
+    });
+    if let Err(err) = result {
+        warn!(
+            "Failed to save the failed combinations to {:?}: {}",
+            path, err
+        );
     }
+}
 
-    // Calculer et afficher le nombre total de combinaisons
-    let total_combinations =
-        (1 << project.features.len()) * (project.extra_features.len() + 1) as u64;
-    println!("Total possible combinations: {}", total_combinations);
-
-    let cached_combinations = if Path::new(cache_file).exists() {
-        let (cached_hash, cached_combinations) =
-            read_cache(cache_file).expect("Failed to read cache");
-        if project.hash == cached_hash {
-            println!("Using cached combinations");
-            cached_combinations
+/// Prints `combinations` one per line, followed by their `count`.
+fn print_combinations(combinations: impl IntoIterator<Item = Vec<String>>, count: u64) {
+    for combo in combinations {
+        if combo.is_empty() {
+            println!("(default features)");
         } else {
-            println!("Features have changed, regenerating combinations");
-            let combinations = generate_combinations(&project);
-            let unique_combinations: HashSet<Vec<String>> = combinations.into_iter().collect();
-            write_cache(cache_file, project.hash, &unique_combinations)
-                .expect("Failed to write cache");
-            unique_combinations
+            println!("{}", combo.join(" "));
         }
-    } else {
-        println!("No cache found, generating combinations");
-        let combinations = generate_combinations(&project);
-        let unique_combinations: HashSet<Vec<String>> = combinations.into_iter().collect();
-        write_cache(cache_file, project.hash, &unique_combinations).expect("Failed to write cache");
-        unique_combinations
-    };
-
-    println!("Total unique combinations: {}", cached_combinations.len());
+    }
+    println!("{} combinations", count);
+}
 
+/// Cleans the project and runs the warm-up step if configured, returning
+/// whether the checks can start. The cache at `cache_file` survives the
+/// cleaning, even inside the target directory.
+async fn prepare(project: &RustProject, cache_file: &Path) -> bool {
     if project.configs.clean {
-        let clean_spinner = ProgressBar::new_spinner();
-        clean_spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("[{elapsed_precise}]{spinner:.green} {msg}")
-                .unwrap(),
-        );
-        clean_spinner.enable_steady_tick(Duration::from_millis(100));
+        let clean_spinner = status_spinner(&project.configs);
         clean_spinner.set_message("Cleaning project");
-        match clear_project(&project).await {
-            Ok(_) => clean_spinner.finish_with_message("Project cleaned successfully"),
+        let saved: Vec<(PathBuf, Vec<u8>)> = [cache_file.to_path_buf(), failures_path(cache_file)]
+            .into_iter()
+            .filter_map(|path| fs::read(&path).ok().map(|contents| (path, contents)))
+            .collect();
+        let cleaned = clear_project(project).await;
+        for (path, contents) in saved {
+            let restored = match path.parent() {
+                Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(&path, contents)),
+                None => fs::write(&path, contents),
+            };
+            if let Err(err) = restored {
+                warn!("Failed to restore {:?} after cleaning: {}", path, err);
+            }
+        }
+        match cleaned {
+            Ok(_) => finish_with_message(
+                &project.configs,
+                &clean_spinner,
+                "Project cleaned successfully",
+            ),
             Err(_) => {
-                clean_spinner.finish_with_message("Failed to clean project");
-                exit(1);
+                finish_with_message(&project.configs, &clean_spinner, "Failed to clean project");
+                return false;
             }
         }
     }
 
-    {
-        let build_spinner = ProgressBar::new_spinner();
-        build_spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("[{elapsed_precise}]{spinner:.green} {msg}")
-                .unwrap(),
+    if project.configs.warmup != Warmup::None {
+        let build_spinner = status_spinner(&project.configs);
+        if let Err(err) = run_cargo_build(&project.path, &project.configs, &build_spinner).await {
+            finish_with_message(&project.configs, &build_spinner, "Warm-up failed");
+            print_error("Warm-up failed", &err);
+            return false;
+        }
+        finish_with_message(&project.configs, &build_spinner, "Warm-up done");
+    }
+
+    true
+}
+
+/// Checks `combinations` and minimizes the failures if configured.
+async fn execute(
+    project: &RustProject,
+    combinations: impl IntoIterator<Item = Vec<String>>,
+    total: u64,
+) -> RunReport {
+    let mut run_report = run_combinations(&project.configs, project, combinations, total).await;
+
+    if project.configs.minimize && !run_report.interrupted {
+        let minimize_spinner = status_spinner(&project.configs);
+        let mut budget = project.configs.minimize_budget;
+        for fail in &mut run_report.failed {
+            if fail.combination.len() < 2 || budget == 0 {
+                continue;
+            }
+            let minimal = minimize_combination(
+                &project.configs,
+                project,
+                &fail.combination,
+                &mut budget,
+                &minimize_spinner,
+            )
+            .await;
+            fail.minimal = Some(minimal);
+        }
+        minimize_spinner.finish_and_clear();
+        if budget == 0 {
+            info!(
+                project.configs,
+                "Minimization budget exhausted, some failures may not be minimal"
+            );
+        }
+    }
+
+    if run_report.stopped_early {
+        info!(
+            project.configs,
+            "Stopped after the first failure (fail_fast)"
+        );
+    }
+    run_report
+}
+
+/// Prints the counts of `report` aligned in a table, with the run's settings.
+fn print_summary(report: &Report, concurrency: usize) {
+    let rows = [
+        ("Combinations", report.results.len().to_string()),
+        ("Passed", report.passed.to_string()),
+        ("Failed", report.failed.to_string()),
+        ("Skipped", report.skipped.to_string()),
+        ("Ignored", report.ignored.to_string()),
+        ("Invalid", report.invalid.to_string()),
+        ("Concurrency", concurrency.to_string()),
+        (
+            "Time",
+            format!("{:.2?}", Duration::from_millis(report.elapsed_ms)),
+        ),
+    ];
+    println!("Summary");
+    for (name, value) in rows {
+        println!("  {:<14}{:>10}", name, value);
+    }
+    if !report.uncovered.is_empty() {
+        println!(
+            "  Features in no combination: {}",
+            report.uncovered.join(", ")
         );
-        build_spinner.enable_steady_tick(Duration::from_millis(100));
-        build_spinner.set_message("Building project for testing");
-        run_cargo_build(&project.path, &ProgressBar::hidden())
-            .await
-            .expect("Failed to build project");
-        build_spinner.finish_with_message("Project built successfully");
-    }
-
-    let multi_progress = MultiProgress::new();
-    let mut progresses = vec![];
-    let mut handles = vec![];
-
-    // Add spinners
-    for i in 0..project.configs.concurrency {
-        let spinner = multi_progress.add(ProgressBar::new_spinner());
-        spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template(
-                    format!(
-                        "[{}/{}] {{spinner:.green}} {{msg}}",
-                        i % project.configs.concurrency + 1,
-                        project.configs.concurrency
-                    )
-                    .as_str(),
-                )
-                .unwrap(),
+    }
+    println!();
+}
+
+/// Prints or writes the reports of `run_report`, returning the exit code of the run.
+fn summarize(cli: &Cli, project: &RustProject, run_report: RunReport, timer: Instant) -> i32 {
+    let interrupted = run_report.interrupted;
+    if !run_report.skipped.is_empty() {
+        info!(
+            project.configs,
+            "{} combinations skipped (superset of known failure)",
+            run_report.skipped.len()
         );
-        spinner.enable_steady_tick(Duration::from_millis(100));
-        progresses.push(spinner);
-    }
-
-    let global_progress = multi_progress.add(ProgressBar::new(cached_combinations.len() as u64));
-    global_progress.enable_steady_tick(Duration::from_millis(100));
-    global_progress.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {wide_bar:0.cyan/blue} Tested {pos}/{len} ({percent}%) | remaining: {eta_precise}").unwrap());
-    for (i, combo) in cached_combinations.into_iter().enumerate() {
-        let path_clone = project.path.clone();
-        let pb = progresses[i % project.configs.concurrency].clone();
-        let gl_pb = global_progress.clone();
-        let handle =
-            tokio::spawn(async move { make_checks(combo, &path_clone, &pb, &gl_pb).await });
-        handles.push(handle);
-    }
-
-    let mut fail_list = vec![];
-
-    for handle in handles {
-        match handle.await.unwrap() {
-            Ok(_) => (),
-            Err((error, combination)) => {
-                fail_list.push(CheckError {
-                    combination,
-                    message: error,
-                });
+    }
+
+    if project.configs.clear_terminal {
+        clear_terminal();
+    }
+
+    let report = run_report.to_report(timer.elapsed());
+    let slowest: Vec<(String, Duration)> = run_report
+        .slowest(SLOWEST_COMBINATIONS)
+        .into_iter()
+        .map(|(combo, elapsed)| (combo.join(" "), elapsed))
+        .collect();
+    for result in &run_report.passed {
+        if project.configs.is_ignored(&result.combination) {
+            warn!(
+                "Ignored combination {:?} now passes, remove it from the ignore list",
+                result.combination.join(" ")
+            );
+        }
+    }
+    let ignored = run_report.ignored;
+    let invalid = run_report.invalid;
+    let fail_list = run_report.failed;
+    // Stale configurations fail the run like broken code does.
+    let failures = fail_list.len() + invalid.len();
+    if cli.output == OutputFormat::Json || cli.report_file.is_some() {
+        match &cli.report_file {
+            Some(path) => {
+                if let Err(err) = report.write_json(path) {
+                    eprintln!("Failed to write report to {:?}: {}", path, err);
+                }
             }
+            None => println!("{}", report.to_json()),
+        }
+    }
+    if let Some(path) = &cli.junit_file {
+        if let Err(err) = report.write_junit(path) {
+            eprintln!("Failed to write JUnit report to {:?}: {}", path, err);
         }
     }
 
-    multi_progress.clear().unwrap();
+    if cli.output == OutputFormat::Tap {
+        print!("{}", report.to_tap());
+    }
+    if cli.output == OutputFormat::Sarif {
+        println!("{}", report.to_sarif(&sarif_uri(&project.manifest_path)));
+    }
 
-    if project.configs.clear_terminal {
-        clear_terminal().await;
+    if cli.output != OutputFormat::Human {
+        return exit_code(project, interrupted, failures);
     }
 
+    if project.configs.quiet {
+        for fail in fail_list.iter().chain(&invalid) {
+            println!("{}", fail.combination.join(" "));
+        }
+    } else {
+        print_results(project, &report, &slowest, &fail_list, interrupted);
+        if !invalid.is_empty() {
+            println!(
+                "\n{} invalid combinations, the configuration names features the package doesn't have:",
+                invalid.len()
+            );
+            for fail in &invalid {
+                let error = fail.message.lines().find(|line| line.starts_with("error"));
+                println!(
+                    "  {:?}: {}",
+                    fail.combination.join(" "),
+                    error.unwrap_or_default()
+                );
+            }
+        }
+        if !ignored.is_empty() {
+            println!("\n{} ignored failures:", ignored.len());
+            for fail in &ignored {
+                println!("  {:?}", fail.combination.join(" "));
+            }
+        }
+        if failures > 0 && project.configs.allow_failures {
+            println!("Failures are allowed, exiting with 0");
+        }
+    }
+    exit_code(project, interrupted, failures)
+}
+
+/// Returns the exit code of a run: 1 when some combinations failed or were
+/// invalid, unless failures are allowed.
+fn exit_code(project: &RustProject, interrupted: bool, failures: usize) -> i32 {
+    if interrupted {
+        INTERRUPTED_EXIT_CODE
+    } else if failures > 0 && !project.configs.allow_failures {
+        1
+    } else {
+        0
+    }
+}
+
+/// Prints the human-readable results of a run: the slowest combinations, the
+/// summary and the details of each failure.
+fn print_results(
+    project: &RustProject,
+    report: &Report,
+    slowest: &[(String, Duration)],
+    fail_list: &[CheckError],
+    interrupted: bool,
+) {
+    if interrupted {
+        println!(
+            "Interrupted, partial results: {} passed, {} failed",
+            report.passed, report.failed
+        );
+    }
+
+    if !slowest.is_empty() {
+        println!("Slowest combinations:");
+        for (combo, elapsed) in slowest {
+            println!("  {:>10.2?}  {}", elapsed, combo);
+        }
+        println!();
+    }
+
+    print_summary(report, project.configs.concurrency);
+
     if fail_list.is_empty() {
-        println!("All checks passed");
-        println!("Done in {:?}", timer.elapsed());
+        if !interrupted && report.invalid == 0 {
+            println!("All checks passed");
+        }
     } else {
         println!("{:?} checks failed", fail_list.len());
-        for fail in fail_list {
-            println!("\nFailed combination: {:?}", fail.combination.join(" "));
-            println!("Error: {}", fail.message);
+        for group in group_by_message(fail_list) {
+            for fail in &group {
+                match &fail.minimal {
+                    Some(minimal) => println!(
+                        "\nFailed combination: {:?} (minimized from {:?})",
+                        minimal.join(" "),
+                        fail.combination.join(" ")
+                    ),
+                    None => println!("\nFailed combination: {:?}", fail.combination.join(" ")),
+                }
+                println!("Command: {}", fail.command);
+                if !fail.stdout.trim().is_empty() {
+                    println!("Output: {}", fail.stdout);
+                }
+            }
+            let diagnostics = &group[0].diagnostics;
+            if project.configs.verbose {
+                println!("Error: see the cargo output above");
+            } else if diagnostics.iter().any(|d| d.level == Level::Error) {
+                let color = project.configs.color.enabled_on_stdout();
+                if group.len() > 1 {
+                    println!(
+                        "Error, the same for these {} combinations: {}",
+                        group.len(),
+                        diagnostics::summary(diagnostics, color)
+                    );
+                } else {
+                    println!("Error: {}", diagnostics::summary(diagnostics, color));
+                }
+                for diagnostic in diagnostics {
+                    println!("  {}", diagnostic.headline(color));
+                    if let Some(location) = &diagnostic.location {
+                        println!("    --> {}", location);
+                    }
+                }
+            } else if group.len() > 1 {
+                println!(
+                    "Error, the same for these {} combinations: {}",
+                    group.len(),
+                    group[0].message
+                );
+            } else {
+                println!("Error: {}", group[0].message);
+            }
             println!("----------------------");
         }
-
-        println!("Done in {:?}", timer.elapsed());
-        exit(1);
     }
 }
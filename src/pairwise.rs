@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+
+/// A pair of features with the value each one takes, `(i, on_i, j, on_j)` with `i < j`.
+type Pair = (usize, bool, usize, bool);
+
+/// Generates a pairwise (2-wise) covering array for `n` boolean features.
+///
+/// Each returned row tells whether the feature at that index is enabled. For
+/// every pair of features, each of the four on/off combinations appears in at
+/// least one row, which is usually far fewer rows than the `2^n` powerset.
+/// Rows are built greedily and the output is deterministic.
+pub fn pairwise_selections(n: usize) -> Vec<Vec<bool>> {
+    if n < 2 {
+        return (0..n).map(|_| vec![true]).collect();
+    }
+
+    let mut uncovered: BTreeSet<Pair> = BTreeSet::new();
+    for i in 0..n {
+        for j in (i + 1)..n {
+            for on_i in [true, false] {
+                for on_j in [true, false] {
+                    uncovered.insert((i, on_i, j, on_j));
+                }
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    while let Some(&(i, on_i, j, on_j)) = uncovered.iter().next() {
+        let mut row: Vec<Option<bool>> = vec![None; n];
+        row[i] = Some(on_i);
+        row[j] = Some(on_j);
+
+        for k in 0..n {
+            if row[k].is_some() {
+                continue;
+            }
+            // Pick the value covering the most pairs with already assigned features.
+            let gain = |value: bool| {
+                row.iter()
+                    .enumerate()
+                    .filter_map(|(m, assigned)| assigned.map(|on_m| (m, on_m)))
+                    .filter(|&(m, on_m)| uncovered.contains(&ordered(k, value, m, on_m)))
+                    .count()
+            };
+            row[k] = Some(gain(true) >= gain(false));
+        }
+
+        let row: Vec<bool> = row.into_iter().map(|value| value.unwrap()).collect();
+        for a in 0..n {
+            for b in (a + 1)..n {
+                uncovered.remove(&(a, row[a], b, row[b]));
+            }
+        }
+        rows.push(row);
+    }
+
+    rows
+}
+
+fn ordered(a: usize, on_a: bool, b: usize, on_b: bool) -> Pair {
+    if a < b {
+        (a, on_a, b, on_b)
+    } else {
+        (b, on_b, a, on_a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairwise_covers_every_pair() {
+        for n in 2..=16 {
+            let rows = pairwise_selections(n);
+            for i in 0..n {
+                for j in (i + 1)..n {
+                    for on_i in [true, false] {
+                        for on_j in [true, false] {
+                            assert!(
+                                rows.iter().any(|row| row[i] == on_i && row[j] == on_j),
+                                "n = {}: pair ({}, {}) = ({}, {}) not covered",
+                                n,
+                                i,
+                                j,
+                                on_i,
+                                on_j
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pairwise_is_smaller_than_powerset() {
+        assert!(pairwise_selections(10).len() < 1 << 10);
+        assert_eq!(pairwise_selections(1), vec![vec![true]]);
+        assert!(pairwise_selections(0).is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+//! Styles of the progress bars and spinners.
+
+use crate::config::GlobalConfig;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Longest message shown next to a spinner, longer ones would wrap and
+/// scramble the other bars.
+pub const SPINNER_MESSAGE_WIDTH: usize = 72;
+
+/// Template of the bar tracking the checked combinations, unless
+/// `progress_template` is set. `{msg}` is the remaining time estimated by [`Eta`].
+pub const DEFAULT_PROGRESS_TEMPLATE: &str =
+    "[{elapsed_precise}] {wide_bar:0.cyan/blue} Tested {pos}/{len} ({percent}%) | remaining: {msg}";
+
+/// Template of the spinner of each worker, unless `spinner_template` is set.
+/// `{prefix}` is the worker number out of the concurrency, e.g. `2/8`.
+pub const DEFAULT_SPINNER_TEMPLATE: &str = "[{prefix}] {spinner:.green} {msg}";
+
+/// How often the progress is printed when bars aren't drawn.
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of latest check durations the remaining time is estimated from.
+const ETA_WINDOW: usize = 20;
+
+/// Estimates the remaining time of a run from the durations of the latest
+/// checks. Unlike `{eta_precise}`, which assumes every combination takes as
+/// long, it follows the checks getting slower as combinations grow.
+#[derive(Debug, Default)]
+pub struct Eta {
+    recent: VecDeque<Duration>,
+}
+
+impl Eta {
+    /// Records the duration of a completed check.
+    pub fn record(&mut self, elapsed: Duration) {
+        if self.recent.len() == ETA_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    /// Returns the time `remaining` checks take, run `concurrency` at once at
+    /// the average duration of the latest ones, none before any completed.
+    pub fn remaining(&self, remaining: u64, concurrency: usize) -> Option<Duration> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let average = self.recent.iter().sum::<Duration>() / self.recent.len() as u32;
+        let rounds = remaining.div_ceil(concurrency.max(1) as u64);
+        Some(average.saturating_mul(u32::try_from(rounds).unwrap_or(u32::MAX)))
+    }
+}
+
+/// Formats an estimate as `HH:MM:SS`, like `{eta_precise}`, or `--:--:--`
+/// when there is none yet.
+pub fn format_eta(eta: Option<Duration>) -> String {
+    match eta {
+        Some(eta) => {
+            let secs = eta.as_secs();
+            format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        }
+        None => "--:--:--".to_string(),
+    }
+}
+
+/// Whether progress bars and spinners are colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Resolves `auto` from `CARGO_TERM_COLOR`, then `NO_COLOR`, then whether
+    /// stderr, where the progress is drawn, is a terminal.
+    pub fn enabled(self) -> bool {
+        self.enabled_on(io::stderr().is_terminal())
+    }
+
+    /// Like [`ColorChoice::enabled`], for the output printed to stdout.
+    pub fn enabled_on_stdout(self) -> bool {
+        self.enabled_on(io::stdout().is_terminal())
+    }
+
+    fn enabled_on(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                match env::var("CARGO_TERM_COLOR")
+                    .ok()
+                    .as_deref()
+                    .and_then(ColorChoice::parse)
+                {
+                    Some(ColorChoice::Always) => return true,
+                    Some(ColorChoice::Never) => return false,
+                    _ => {}
+                }
+                if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                    return false;
+                }
+                terminal
+            }
+        }
+    }
+}
+
+/// Returns whether live progress bars are drawn: not with `--no-progress` or
+/// `--quiet`, on CI (when `CI` is set) or when stderr isn't a terminal.
+pub fn interactive(configs: &GlobalConfig) -> bool {
+    !configs.no_progress
+        && !configs.quiet
+        && env::var_os("CI").is_none_or(|value| value.is_empty() || value == "false")
+        && io::stderr().is_terminal()
+}
+
+/// Style of a progress bar drawn with `template`, colored according to `configs`.
+pub fn bar_style(configs: &GlobalConfig, template: &str) -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(&colored(template, configs.color.enabled()))
+        .unwrap()
+}
+
+/// Style of a spinner drawn with `template`, colored according to `configs`.
+pub fn spinner_style(configs: &GlobalConfig, template: &str) -> ProgressStyle {
+    ProgressStyle::default_spinner()
+        .template(&colored(template, configs.color.enabled()))
+        .unwrap()
+}
+
+/// Template of the bar tracking the checked combinations.
+pub fn progress_template(configs: &GlobalConfig) -> &str {
+    configs
+        .progress_template
+        .as_deref()
+        .unwrap_or(DEFAULT_PROGRESS_TEMPLATE)
+}
+
+/// Template of the spinner of each worker.
+pub fn spinner_template(configs: &GlobalConfig) -> &str {
+    configs
+        .spinner_template
+        .as_deref()
+        .unwrap_or(DEFAULT_SPINNER_TEMPLATE)
+}
+
+/// Returns a ticking spinner showing the elapsed time and a message, hidden
+/// when not [`interactive`].
+pub fn status_spinner(configs: &GlobalConfig) -> ProgressBar {
+    if !interactive(configs) {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(spinner_style(
+        configs,
+        "[{elapsed_precise}]{spinner:.green} {msg}",
+    ));
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// Finishes `pb` with `message`, printed as a plain line when `pb` is hidden
+/// unless `--quiet` is set.
+pub fn finish_with_message(configs: &GlobalConfig, pb: &ProgressBar, message: &'static str) {
+    if pb.is_hidden() && !configs.quiet {
+        println!("{}", message);
+    }
+    pb.finish_with_message(message);
+}
+
+/// Prints `<action> <pos>/<len>` from `pb` every few seconds, as a plain text
+/// stand-in for a hidden progress bar. Lines are only printed when the
+/// position moved, until the returned task is aborted.
+pub fn print_periodically(pb: ProgressBar, action: &'static str) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PLAIN_PROGRESS_INTERVAL);
+        interval.tick().await;
+        let mut last = 0;
+        loop {
+            interval.tick().await;
+            if pb.position() != last {
+                last = pb.position();
+                println!("{}", plain_progress(&pb, action));
+            }
+        }
+    })
+}
+
+/// Formats the position of `pb` as `<action> <pos>/<len>`.
+pub fn plain_progress(pb: &ProgressBar, action: &str) -> String {
+    match pb.length() {
+        Some(length) => format!("{} {}/{}", action, pb.position(), length),
+        None => format!("{} {}", action, pb.position()),
+    }
+}
+
+/// Returns `template`, without the style of its placeholders (`{spinner:.green}`
+/// becomes `{spinner}`) unless `color` is set.
+fn colored(template: &str, color: bool) -> String {
+    if color {
+        return template.to_string();
+    }
+    let mut plain = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        plain.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..end];
+        match placeholder.split_once(':') {
+            Some((key, spec)) if spec.contains('.') => {
+                plain.push('{');
+                plain.push_str(key);
+                plain.push('}');
+            }
+            _ => plain.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+/// Shortens `message` to at most `width` characters, ending with an ellipsis
+/// when it was cut.
+pub fn truncate_message(message: &str, width: usize) -> String {
+    if message.chars().count() <= width {
+        return message.to_string();
+    }
+    let mut truncated: String = message.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_messages_are_truncated_with_an_ellipsis() {
+        assert_eq!(truncate_message("cargo check", 20), "cargo check");
+        assert_eq!(truncate_message("cargo check", 11), "cargo check");
+        assert_eq!(truncate_message("cargo check", 8), "cargo c…");
+        assert_eq!(truncate_message("é".repeat(10).as_str(), 4), "ééé…");
+    }
+
+    #[test]
+    fn colors_are_stripped_from_templates() {
+        let template = "[{elapsed_precise}] {wide_bar:0.cyan/blue} {spinner:.green} {pos}/{len}";
+
+        assert_eq!(
+            colored(template, false),
+            "[{elapsed_precise}] {wide_bar} {spinner} {pos}/{len}"
+        );
+        assert_eq!(colored(template, true), template);
+        assert_eq!(colored("[1/4] {{ {msg}", false), "[1/4] {{ {msg}");
+    }
+
+    #[test]
+    fn no_progress_hides_spinners() {
+        let mut configs = crate::test_support::global_config();
+        configs.no_progress = true;
+
+        assert!(!interactive(&configs));
+        assert!(status_spinner(&configs).is_hidden());
+    }
+
+    #[test]
+    fn plain_progress_shows_position_and_length() {
+        let pb = ProgressBar::hidden();
+        pb.set_length(12);
+        pb.inc(5);
+
+        assert_eq!(plain_progress(&pb, "Tested"), "Tested 5/12");
+    }
+
+    #[test]
+    fn eta_follows_the_latest_durations() {
+        let mut eta = Eta::default();
+        assert_eq!(format_eta(eta.remaining(10, 2)), "--:--:--");
+
+        for _ in 0..ETA_WINDOW {
+            eta.record(Duration::from_secs(1));
+        }
+        assert_eq!(eta.remaining(10, 2), Some(Duration::from_secs(5)));
+        assert_eq!(eta.remaining(3, 2), Some(Duration::from_secs(2)));
+
+        // Slower checks replace the first ones.
+        for _ in 0..ETA_WINDOW / 2 {
+            eta.record(Duration::from_secs(3));
+        }
+        assert_eq!(eta.remaining(10, 2), Some(Duration::from_secs(10)));
+        assert_eq!(format_eta(Some(Duration::from_secs(3725))), "01:02:05");
+    }
+
+    #[test]
+    fn explicit_choices_ignore_the_environment() {
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+    }
+}
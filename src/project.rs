@@ -0,0 +1,803 @@
+use crate::changes::features_enabling;
+use crate::config::{Config, GlobalConfig};
+use crate::error::{MultiCheckError, Result};
+use cargo_metadata::MetadataCommand;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// A cargo project along with the features selected for testing.
+#[derive(Clone)]
+pub struct RustProject {
+    pub hash: u64,
+    pub configs: GlobalConfig,
+    pub path: PathBuf,
+    /// The `Cargo.toml` of the checked package, the member selected with
+    /// `--package` in a workspace.
+    pub manifest_path: PathBuf,
+    /// Cargo's target directory, `target` in the workspace root unless
+    /// `CARGO_TARGET_DIR` or `build.target-dir` move it.
+    pub target_dir: PathBuf,
+    pub features: Vec<String>,
+    pub extra_features: Vec<String>,
+    /// Features of the crate enabled by each feature of `Cargo.toml`, including
+    /// the implicit features of optional dependencies.
+    pub dependencies: HashMap<String, Vec<String>>,
+}
+
+impl RustProject {
+    pub fn new(path: &str, configs: &Config, cargo: Option<&String>) -> Result<Self> {
+        let full_path = Path::new(path).canonicalize()?;
+        let mut cargo_toml = match cargo {
+            Some(c) => Path::new(c).canonicalize()?,
+            None => full_path.join("Cargo.toml"),
+        };
+        let mut global_config = configs.global.clone();
+        // Cargo runs in the project directory, which may not hold the manifest.
+        if cargo.is_some() {
+            global_config.manifest_path = Some(cargo_toml.clone());
+        }
+        match &global_config.package {
+            Some(package) => cargo_toml = package_manifest(&cargo_toml, package)?,
+            None => reject_virtual_manifest(&cargo_toml)?,
+        }
+
+        let (features, extra) = categorize_features(configs);
+        debug!(strict = ?features, extra = ?extra, "categorized features");
+
+        // Members of a workspace often inherit parts of their manifest, which
+        // only cargo resolves.
+        let dependencies = if global_config.use_cargo_metadata || global_config.package.is_some() {
+            match metadata_dependencies(&cargo_toml) {
+                Ok(dependencies) => dependencies,
+                Err(err) => {
+                    warn!(
+                        "cargo metadata failed ({}), reading {:?} instead",
+                        err, cargo_toml
+                    );
+                    extract_dependencies(&cargo_toml)?
+                }
+            }
+        } else {
+            extract_dependencies(&cargo_toml)?
+        };
+        debug!(?dependencies, "extracted feature dependencies");
+        if let Some(changed) = &mut global_config.changed_features {
+            *changed = features_enabling(changed, &dependencies, &global_config.requires);
+            changed.retain(|feature| features.contains(feature) || extra.contains(feature));
+            debug!(?changed, "tested features affected by changes");
+        }
+        reject_unknown_features(&cargo_toml, &features, &extra, &dependencies)?;
+        if global_config.warn_untested_features {
+            warn_untested_features(&cargo_toml, &features, &extra);
+        }
+        let rustc = rustc_version(&full_path, global_config.toolchain.as_deref());
+        let target_dir = target_directory(&full_path, &cargo_toml);
+        let hash = hash_features(
+            &features,
+            &extra,
+            &dependencies,
+            &global_config,
+            rustc.as_deref(),
+        );
+        Ok(Self {
+            hash,
+            configs: global_config,
+            path: full_path,
+            manifest_path: cargo_toml,
+            target_dir,
+            features,
+            extra_features: extra,
+            dependencies,
+        })
+    }
+}
+
+/// Fails when a feature of the configuration isn't declared by `cargo_toml`,
+/// cargo would reject every combination containing it.
+fn reject_unknown_features(
+    cargo_toml: &Path,
+    features: &[String],
+    extra: &[String],
+    dependencies: &HashMap<String, Vec<String>>,
+) -> Result<()> {
+    let mut unknown: Vec<String> = features
+        .iter()
+        .chain(extra)
+        .filter(|feature| !dependencies.contains_key(*feature))
+        .cloned()
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort();
+    Err(MultiCheckError::UnknownFeatures {
+        manifest: cargo_toml.to_path_buf(),
+        features: unknown,
+    })
+}
+
+/// Warns, in a single line, about the features of `cargo_toml` that are
+/// neither in `features` nor in `extra`.
+fn warn_untested_features(cargo_toml: &Path, features: &[String], extra: &[String]) {
+    let Ok(declared) = manifest_features(cargo_toml) else {
+        return;
+    };
+    let untested: Vec<String> = declared
+        .into_iter()
+        .filter(|feature| !features.contains(feature) && !extra.contains(feature))
+        .collect();
+    if !untested.is_empty() {
+        warn!(
+            "{} features not tested: {}",
+            untested.len(),
+            untested.join(", ")
+        );
+    }
+}
+
+/// Returns the strict and extra features of `config`, sorted, from the
+/// `[features]` table or the arrays of `[selection]`.
+fn categorize_features(config: &Config) -> (Vec<String>, Vec<String>) {
+    let (mut main_features, mut extra_features) = match &config.selection {
+        Some(selection) => (selection.strict.clone(), selection.extra.clone()),
+        None => (Vec::new(), Vec::new()),
+    };
+
+    for (feature, details) in &config.features {
+        if details.strict {
+            main_features.push(feature.clone());
+        } else {
+            extra_features.push(feature.clone());
+        }
+    }
+
+    main_features.sort();
+    main_features.dedup();
+    extra_features.sort();
+    extra_features.dedup();
+    (main_features, extra_features)
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+    #[serde(default)]
+    features: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    dependencies: HashMap<String, toml::Value>,
+    #[serde(default, rename = "build-dependencies")]
+    build_dependencies: HashMap<String, toml::Value>,
+    #[serde(default)]
+    target: HashMap<String, TargetManifest>,
+}
+
+#[derive(Deserialize)]
+struct TargetManifest {
+    #[serde(default)]
+    dependencies: HashMap<String, toml::Value>,
+    #[serde(default, rename = "build-dependencies")]
+    build_dependencies: HashMap<String, toml::Value>,
+}
+
+impl CargoManifest {
+    /// Returns the names of the optional dependencies, which can be enabled by features.
+    fn optional_dependencies(&self) -> HashSet<&String> {
+        let targets = self
+            .target
+            .values()
+            .flat_map(|target| target.dependencies.iter().chain(&target.build_dependencies));
+        self.dependencies
+            .iter()
+            .chain(&self.build_dependencies)
+            .chain(targets)
+            .filter(|(_, value)| value.get("optional").and_then(toml::Value::as_bool) == Some(true))
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+/// Returns the sorted names of the features declared in the `[features]`
+/// table of `cargo_toml`, `default` excluded.
+pub fn manifest_features(cargo_toml: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(cargo_toml)?;
+    let manifest: CargoManifest =
+        toml::from_str(&contents).map_err(|err| manifest_error(cargo_toml, err))?;
+    let mut features: Vec<String> = manifest
+        .features
+        .into_keys()
+        .filter(|feature| feature != "default")
+        .collect();
+    features.sort();
+    Ok(features)
+}
+
+/// An entry of a feature's list in `Cargo.toml`.
+#[derive(Debug, PartialEq)]
+enum FeatureValue<'a> {
+    /// `feat`: enables another feature of the crate.
+    Feature(&'a str),
+    /// `dep:pkg`: enables the optional dependency `pkg`.
+    Dependency(&'a str),
+    /// `pkg/feat` or, when `weak`, `pkg?/feat`: enables `feat` on the dependency
+    /// `pkg`, and the dependency itself unless weak.
+    DependencyFeature {
+        dependency: &'a str,
+        feature: &'a str,
+        weak: bool,
+    },
+}
+
+impl<'a> FeatureValue<'a> {
+    fn parse(value: &'a str) -> Self {
+        if let Some(dependency) = value.strip_prefix("dep:") {
+            return FeatureValue::Dependency(dependency);
+        }
+        match value.split_once('/') {
+            Some((dependency, feature)) => match dependency.strip_suffix('?') {
+                Some(dependency) => FeatureValue::DependencyFeature {
+                    dependency,
+                    feature,
+                    weak: true,
+                },
+                None => FeatureValue::DependencyFeature {
+                    dependency,
+                    feature,
+                    weak: false,
+                },
+            },
+            None => FeatureValue::Feature(value),
+        }
+    }
+}
+
+fn extract_dependencies(file_path: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let contents = fs::read_to_string(file_path)?;
+    parse_dependencies(&contents).map_err(|err| manifest_error(file_path, err))
+}
+
+fn manifest_error(cargo_toml: &Path, err: toml::de::Error) -> MultiCheckError {
+    MultiCheckError::ManifestParse {
+        path: cargo_toml.to_path_buf(),
+        message: err.to_string(),
+    }
+}
+
+fn parse_dependencies(
+    contents: &str,
+) -> std::result::Result<HashMap<String, Vec<String>>, toml::de::Error> {
+    let manifest: CargoManifest = toml::from_str(contents)?;
+
+    // Optional dependencies never referred to as `dep:x` get an implicit feature `x`.
+    let mut implicit_features: HashSet<String> = manifest
+        .optional_dependencies()
+        .into_iter()
+        .cloned()
+        .collect();
+    for value in manifest.features.values().flatten() {
+        if let FeatureValue::Dependency(dependency) = FeatureValue::parse(value) {
+            implicit_features.remove(dependency);
+        }
+    }
+
+    Ok(feature_graph(&manifest.features, &implicit_features))
+}
+
+/// Reads the features of the package at `cargo_toml` from `cargo metadata`,
+/// which resolves workspace inheritance and the implicit features of optional
+/// dependencies itself.
+fn metadata_dependencies(cargo_toml: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(cargo_toml)
+        .no_deps()
+        .exec()?;
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| package.manifest_path == cargo_toml)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no package has the manifest {:?}", cargo_toml),
+            )
+        })?;
+
+    let mut table: HashMap<String, Vec<String>> = package
+        .features
+        .iter()
+        .map(|(name, values)| (name.clone(), values.clone()))
+        .collect();
+    // Implicit features are listed as `x = ["dep:x"]`, take them out of the
+    // table so that they are handled as with a parsed manifest.
+    let implicit_features: HashSet<String> = package
+        .dependencies
+        .iter()
+        .filter(|dependency| dependency.optional)
+        .map(|dependency| {
+            dependency
+                .rename
+                .clone()
+                .unwrap_or_else(|| dependency.name.to_string())
+        })
+        .filter(|name| table.get(name) == Some(&vec![format!("dep:{}", name)]))
+        .collect();
+    table.retain(|name, _| !implicit_features.contains(name));
+
+    Ok(feature_graph(&table, &implicit_features))
+}
+
+/// Returns the manifest of the member `package` of the workspace of `cargo_toml`.
+/// Returns the target directory cargo uses for `cargo_toml` when run in
+/// `project_dir`, as `cargo metadata` resolves it, else the `target`
+/// directory of `project_dir`.
+fn target_directory(project_dir: &Path, cargo_toml: &Path) -> PathBuf {
+    let metadata = MetadataCommand::new()
+        .manifest_path(cargo_toml)
+        .current_dir(project_dir)
+        .no_deps()
+        .exec();
+    match metadata {
+        Ok(metadata) => metadata.target_directory.into_std_path_buf(),
+        Err(err) => {
+            debug!(%err, "cargo metadata failed, using the target directory of the project");
+            project_dir.join("target")
+        }
+    }
+}
+
+pub fn package_manifest(cargo_toml: &Path, package: &str) -> Result<PathBuf> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(cargo_toml)
+        .no_deps()
+        .exec()?;
+    let members = metadata.workspace_packages();
+    match members
+        .iter()
+        .find(|member| member.name.as_str() == package)
+    {
+        Some(member) => Ok(member.manifest_path.clone().into_std_path_buf()),
+        None => Err(MultiCheckError::PackageNotFound {
+            package: package.to_string(),
+            members: member_names(&members),
+        }),
+    }
+}
+
+/// Fails with the list of members when `cargo_toml` is a workspace root
+/// without a package of its own, which has no features to check.
+fn reject_virtual_manifest(cargo_toml: &Path) -> Result<()> {
+    let Ok(contents) = fs::read_to_string(cargo_toml) else {
+        return Ok(());
+    };
+    let Ok(manifest) = toml::from_str::<toml::Table>(&contents) else {
+        return Ok(());
+    };
+    if manifest.contains_key("package") || !manifest.contains_key("workspace") {
+        return Ok(());
+    }
+    let members = match MetadataCommand::new()
+        .manifest_path(cargo_toml)
+        .no_deps()
+        .exec()
+    {
+        Ok(metadata) => member_names(&metadata.workspace_packages()),
+        Err(_) => "unknown, cargo metadata failed".to_string(),
+    };
+    Err(MultiCheckError::VirtualManifest {
+        manifest: cargo_toml.to_path_buf(),
+        members,
+    })
+}
+
+fn member_names(members: &[&cargo_metadata::Package]) -> String {
+    let mut names: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+    names.sort();
+    names.join(", ")
+}
+
+/// Builds the features enabled by each feature of the `[features]` table,
+/// given the implicit features of the optional dependencies.
+fn feature_graph(
+    table: &HashMap<String, Vec<String>>,
+    implicit_features: &HashSet<String>,
+) -> HashMap<String, Vec<String>> {
+    let mut dependencies: HashMap<String, Vec<String>> = implicit_features
+        .iter()
+        .map(|name| (name.to_string(), Vec::new()))
+        .collect();
+    for (feature, deps) in table {
+        let deps: Vec<String> = deps
+            .iter()
+            .filter(|dep| !dep.is_empty())
+            .filter_map(|dep| match FeatureValue::parse(dep) {
+                FeatureValue::Feature(feature) => Some(feature.to_string()),
+                // `pkg/feat` also turns on the implicit feature of `pkg`, if it has one.
+                FeatureValue::DependencyFeature {
+                    dependency,
+                    weak: false,
+                    ..
+                } if implicit_features.contains(dependency) => Some(dependency.to_string()),
+                FeatureValue::Dependency(_) | FeatureValue::DependencyFeature { .. } => None,
+            })
+            .collect();
+        dependencies.insert(feature.clone(), deps);
+    }
+
+    dependencies
+}
+
+fn hash_features(
+    features: &[String],
+    extra_features: &[String],
+    dependencies: &HashMap<String, Vec<String>>,
+    configs: &GlobalConfig,
+    rustc_version: Option<&str>,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(version) = rustc_version {
+        version.hash(&mut hasher);
+    }
+    features.hash(&mut hasher);
+    extra_features.hash(&mut hasher);
+    configs.strategy.hash(&mut hasher);
+    configs.max_combination_size.hash(&mut hasher);
+    configs.keep_default_features.hash(&mut hasher);
+    configs.exclusive.hash(&mut hasher);
+    let mut requires: Vec<_> = configs.requires.iter().collect();
+    requires.sort();
+    requires.hash(&mut hasher);
+    // Implications through untested features matter too, so hash every edge.
+    let mut edges: Vec<_> = dependencies.iter().collect();
+    edges.sort();
+    edges.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the `rustc --version` of `toolchain`, or of the toolchain active in
+/// `project_dir` when unset, if rustc can be invoked.
+fn rustc_version(project_dir: &Path, toolchain: Option<&str>) -> Option<String> {
+    let mut command = std::process::Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        command.arg(format!("+{}", toolchain.trim_start_matches('+')));
+    }
+    let output = command.arg("--version").current_dir(project_dir).output();
+    match output {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        _ => {
+            warn!("Failed to get rustc version, cache won't track the toolchain");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CheckCommand;
+    use crate::runner::check_args;
+    use crate::test_support::{global_config, strings};
+
+    #[test]
+    fn selection_and_features_table_categorize_alike() {
+        let table = Config::parse(
+            "[global]\nclean = false\nclear_terminal = false\n\
+             [features]\nb = { strict = true }\na = { strict = true }\nc = { strict = false }\n",
+        )
+        .unwrap();
+        let selection = Config::parse(
+            "[global]\nclean = false\nclear_terminal = false\n\
+             [selection]\nstrict = [\"b\", \"a\"]\nextra = [\"c\"]\n",
+        )
+        .unwrap();
+
+        let expected = (strings(&["a", "b"]), strings(&["c"]));
+        assert_eq!(categorize_features(&table), expected);
+        assert_eq!(categorize_features(&selection), expected);
+    }
+
+    #[test]
+    fn parse_dependencies_handles_multiline_arrays() {
+        let manifest = r#"
+[package]
+name = "demo"
+
+[features]
+default = ["a"]
+a = []
+b = ["a", "dep:serde"]
+c = [
+  "a",
+  "b",
+]
+
+[dependencies]
+serde = { version = "1", optional = true }
+"#;
+        let dependencies = parse_dependencies(manifest).unwrap();
+
+        assert_eq!(dependencies["default"], vec!["a"]);
+        assert!(dependencies["a"].is_empty());
+        assert_eq!(dependencies["b"], vec!["a"]);
+        assert_eq!(dependencies["c"], vec!["a", "b"]);
+    }
+
+    #[test]
+    fn metadata_dependencies_match_the_manifest_parser() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write(
+            "helper/Cargo.toml",
+            "[package]\nname = \"helper\"\nversion = \"0.1.0\"\n\n[features]\nstd = []\n",
+        );
+        write("helper/src/lib.rs", "");
+        let manifest = r#"
+[package]
+name = "demo"
+version = "0.1.0"
+
+[features]
+a = []
+b = ["a", "helper/std"]
+
+[dependencies]
+helper = { path = "../helper", optional = true }
+"#;
+        write("demo/Cargo.toml", manifest);
+        write("demo/src/lib.rs", "");
+        let cargo_toml = dir.path().join("demo/Cargo.toml").canonicalize().unwrap();
+
+        let dependencies = metadata_dependencies(&cargo_toml).unwrap();
+
+        assert_eq!(dependencies, parse_dependencies(manifest).unwrap());
+        assert_eq!(dependencies["b"], vec!["a", "helper"]);
+        assert!(dependencies["helper"].is_empty());
+    }
+
+    #[test]
+    fn target_dir_is_the_one_cargo_uses() {
+        // The variable takes precedence over the configuration.
+        if std::env::var_os("CARGO_TARGET_DIR").is_some() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("Cargo.toml", "[workspace]\nmembers = [\"member\"]\n");
+        write(
+            "member/Cargo.toml",
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\n",
+        );
+        write("member/src/lib.rs", "");
+        let root = dir.path().canonicalize().unwrap();
+        let member = root.join("member");
+
+        assert_eq!(
+            target_directory(&member, &member.join("Cargo.toml")),
+            root.join("target")
+        );
+        write(".cargo/config.toml", "[build]\ntarget-dir = \"out\"\n");
+        assert_eq!(
+            target_directory(&member, &member.join("Cargo.toml")),
+            root.join("out")
+        );
+        let missing = root.join("missing");
+        assert_eq!(
+            target_directory(&missing, &missing.join("Cargo.toml")),
+            missing.join("target")
+        );
+    }
+
+    #[test]
+    fn unknown_configured_feature_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\n\n[features]\nstd = []\n\n[dependencies]\nserde = { version = \"1\", optional = true }\n",
+        )
+        .unwrap();
+        let config = |features: &str| {
+            Config::parse(&format!(
+                "[global]\nclean = false\nclear_terminal = false\n[features]\n{}",
+                features
+            ))
+            .unwrap()
+        };
+        let path = dir.path().to_str().unwrap();
+
+        let known = config("std = { strict = true }\nserde = { strict = false }\n");
+        assert!(RustProject::new(path, &known, None).is_ok());
+
+        let typo = config("std = { strict = true }\nsdt = { strict = false }\n");
+        let err = RustProject::new(path, &typo, None).err().unwrap();
+        assert!(
+            matches!(&err, MultiCheckError::UnknownFeatures { features, .. } if *features == ["sdt"])
+        );
+        assert!(err.to_string().contains("sdt"), "{}", err);
+    }
+
+    #[test]
+    fn manifest_in_a_subdirectory_is_passed_to_cargo() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("crate")).unwrap();
+        fs::write(
+            dir.path().join("crate/Cargo.toml"),
+            "[package]\nname = \"demo\"\n\n[features]\nstd = []\n",
+        )
+        .unwrap();
+        let config = Config::parse(
+            "[global]\nclean = false\nclear_terminal = false\n[features]\nstd = { strict = true }\n",
+        )
+        .unwrap();
+        let manifest = dir.path().join("crate/Cargo.toml").display().to_string();
+
+        let project =
+            RustProject::new(dir.path().to_str().unwrap(), &config, Some(&manifest)).unwrap();
+
+        assert_eq!(project.path, dir.path().canonicalize().unwrap());
+        assert_eq!(project.features, strings(&["std"]));
+        let manifest = Path::new(&manifest).canonicalize().unwrap();
+        assert_eq!(project.configs.manifest_path.as_ref(), Some(&manifest));
+        let args = check_args(&project.configs, CheckCommand::Check, &strings(&["std"]));
+        assert_eq!(
+            args[..3],
+            strings(&["check", "--manifest-path", manifest.to_str().unwrap()])
+        );
+    }
+
+    #[test]
+    fn workspace_members_are_selected_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("Cargo.toml", "[workspace]\nmembers = [\"one\", \"two\"]\n");
+        for member in ["one", "two"] {
+            write(
+                &format!("{}/Cargo.toml", member),
+                &format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", member),
+            );
+            write(&format!("{}/src/lib.rs", member), "");
+        }
+        let root = dir.path().canonicalize().unwrap().join("Cargo.toml");
+
+        let err = reject_virtual_manifest(&root).unwrap_err();
+        assert!(err.to_string().contains("one, two"), "{}", err);
+        assert_eq!(
+            package_manifest(&root, "two").unwrap(),
+            root.parent().unwrap().join("two/Cargo.toml")
+        );
+        let err = package_manifest(&root, "three").unwrap_err();
+        assert!(matches!(err, MultiCheckError::PackageNotFound { .. }));
+        assert!(reject_virtual_manifest(&root.parent().unwrap().join("one/Cargo.toml")).is_ok());
+    }
+
+    #[test]
+    fn feature_values_parse_every_syntax() {
+        assert_eq!(FeatureValue::parse("a"), FeatureValue::Feature("a"));
+        assert_eq!(
+            FeatureValue::parse("dep:serde"),
+            FeatureValue::Dependency("serde")
+        );
+        assert_eq!(
+            FeatureValue::parse("serde/derive"),
+            FeatureValue::DependencyFeature {
+                dependency: "serde",
+                feature: "derive",
+                weak: false
+            }
+        );
+        assert_eq!(
+            FeatureValue::parse("serde?/derive"),
+            FeatureValue::DependencyFeature {
+                dependency: "serde",
+                feature: "derive",
+                weak: true
+            }
+        );
+    }
+
+    #[test]
+    fn parse_dependencies_only_keeps_crate_features() {
+        let manifest = r#"
+[package]
+name = "demo"
+
+[features]
+a = ["dep:serde", "serde/derive"]
+b = ["log/std", "rand?/std"]
+c = ["tokio?/rt"]
+d = ["rand"]
+
+[dependencies]
+serde = { version = "1", optional = true }
+log = { version = "0.4", optional = true }
+tokio = { version = "1", optional = true }
+anyhow = "1"
+
+[target.'cfg(unix)'.dependencies]
+rand = { version = "0.8", optional = true }
+"#;
+
+        let dependencies = parse_dependencies(manifest).unwrap();
+
+        // `serde` has no implicit feature since it is referred to as `dep:serde`.
+        assert!(!dependencies.contains_key("serde"));
+        assert!(dependencies["a"].is_empty());
+        // `log/std` enables the implicit `log` feature, the weak `rand?/std` doesn't.
+        assert_eq!(dependencies["b"], vec!["log"]);
+        assert!(dependencies["c"].is_empty());
+        assert_eq!(dependencies["d"], vec!["rand"]);
+        assert!(dependencies.contains_key("tokio"));
+        assert!(!dependencies.contains_key("anyhow"));
+    }
+
+    #[test]
+    fn hash_features_changes_with_extra_features() {
+        let configs = global_config();
+        let dependencies = HashMap::new();
+        let base = hash_features(
+            &strings(&["a", "b"]),
+            &strings(&["c"]),
+            &dependencies,
+            &configs,
+            None,
+        );
+
+        let added = hash_features(
+            &strings(&["a", "b"]),
+            &strings(&["c", "d"]),
+            &dependencies,
+            &configs,
+            None,
+        );
+        let toggled = hash_features(
+            &strings(&["a"]),
+            &strings(&["b", "c"]),
+            &dependencies,
+            &configs,
+            None,
+        );
+
+        // Filters apply to the cached combinations, sharing one cache.
+        let mut filtered_configs = global_config();
+        filtered_configs.skip_features = strings(&["a"]);
+        filtered_configs.only_features = strings(&["b"]);
+        filtered_configs.changed_features = Some(strings(&["b"]));
+        let filtered = hash_features(
+            &strings(&["a", "b"]),
+            &strings(&["c"]),
+            &dependencies,
+            &filtered_configs,
+            None,
+        );
+
+        assert_ne!(base, added);
+        assert_ne!(base, toggled);
+        assert_eq!(base, filtered);
+    }
+
+    #[test]
+    fn hash_features_changes_with_rustc_version() {
+        let configs = global_config();
+        let dependencies = HashMap::new();
+        let features = strings(&["a"]);
+        let hash = |rustc| hash_features(&features, &[], &dependencies, &configs, rustc);
+
+        assert_ne!(hash(Some("rustc 1.80.0")), hash(Some("rustc 1.81.0")));
+        assert_ne!(hash(Some("rustc 1.80.0")), hash(None));
+    }
+}
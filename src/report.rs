@@ -0,0 +1,856 @@
+use crate::diagnostics::Diagnostic;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, io};
+
+/// Outcome of a single feature combination.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// Not checked, being a superset of a failed combination.
+    Skipped,
+    /// Failed, but listed in the `ignore` configuration.
+    Ignored,
+    /// Rejected by cargo for naming a feature the package doesn't have.
+    Invalid,
+}
+
+/// Result of running cargo against one feature combination.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    /// Features passed to `--features`, empty for the default features.
+    pub combination: Vec<String>,
+    pub status: CheckStatus,
+    /// Time spent running the cargo command, in milliseconds.
+    pub elapsed_ms: u64,
+    /// Captured stderr, only present for failed, ignored and invalid combinations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    /// Captured stdout, only present for failed combinations that printed something.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    /// Smallest failing subset of `combination`, only present when minimizing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimal: Option<Vec<String>>,
+    /// Cargo command that failed, only present for failed combinations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Compiler errors and warnings of failed combinations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    /// Passed last time with unchanged sources, so not checked again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl CheckResult {
+    pub fn passed(combination: Vec<String>, elapsed: Duration) -> Self {
+        Self {
+            combination,
+            status: CheckStatus::Passed,
+            elapsed_ms: elapsed.as_millis() as u64,
+            stderr: None,
+            stdout: None,
+            minimal: None,
+            command: None,
+            diagnostics: Vec::new(),
+            cached: false,
+        }
+    }
+
+    /// A combination that passed last time and was not checked again.
+    pub fn cached(combination: Vec<String>) -> Self {
+        Self {
+            cached: true,
+            ..Self::passed(combination, Duration::ZERO)
+        }
+    }
+
+    pub fn skipped(combination: Vec<String>) -> Self {
+        Self {
+            combination,
+            status: CheckStatus::Skipped,
+            elapsed_ms: 0,
+            stderr: None,
+            stdout: None,
+            minimal: None,
+            command: None,
+            diagnostics: Vec::new(),
+            cached: false,
+        }
+    }
+
+    pub fn failed(
+        combination: Vec<String>,
+        elapsed: Duration,
+        stderr: String,
+        stdout: String,
+    ) -> Self {
+        Self {
+            combination,
+            status: CheckStatus::Failed,
+            elapsed_ms: elapsed.as_millis() as u64,
+            stderr: Some(stderr),
+            stdout: Some(stdout).filter(|stdout| !stdout.trim().is_empty()),
+            minimal: None,
+            command: None,
+            diagnostics: Vec::new(),
+            cached: false,
+        }
+    }
+}
+
+/// A combination that failed, with the captured cargo output.
+#[derive(Debug, Clone)]
+pub struct CheckError {
+    pub combination: Vec<String>,
+    /// The cargo command line that was run, ready to be copy-pasted.
+    pub command: String,
+    /// Captured stderr of the cargo command.
+    pub message: String,
+    /// Captured stdout of the cargo command.
+    pub stdout: String,
+    /// Compiler errors and warnings reported by the cargo command.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Time spent running the cargo command.
+    pub elapsed: Duration,
+    /// Smallest subset of `combination` found to fail too, when minimizing.
+    pub minimal: Option<Vec<String>>,
+}
+
+/// Groups `failed` by error message, in order of first appearance, so that a
+/// single root cause breaking many combinations is reported once. Messages
+/// only differing in whitespace are grouped together.
+pub fn group_by_message(failed: &[CheckError]) -> Vec<Vec<&CheckError>> {
+    let mut groups: Vec<(String, Vec<&CheckError>)> = Vec::new();
+    for fail in failed {
+        let key = fail
+            .message
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match groups.iter_mut().find(|(message, _)| *message == key) {
+            Some((_, group)) => group.push(fail),
+            None => groups.push((key, vec![fail])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Outcome of checking a set of combinations.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub passed: Vec<CheckResult>,
+    pub failed: Vec<CheckError>,
+    /// Failures of the combinations listed in the `ignore` configuration.
+    pub ignored: Vec<CheckError>,
+    /// Combinations cargo rejected for naming features the package doesn't
+    /// have, the configuration being out of date with the manifest.
+    pub invalid: Vec<CheckError>,
+    /// Combinations skipped as supersets of a failed combination.
+    pub skipped: Vec<Vec<String>>,
+    /// Whether fail-fast stopped the run before every combination was checked.
+    pub stopped_early: bool,
+    /// Whether the run was interrupted with Ctrl-C.
+    pub interrupted: bool,
+    /// Tested features that none of the combinations contains.
+    pub uncovered: Vec<String>,
+}
+
+impl RunReport {
+    /// Returns up to `count` combinations, passed or failed, that took the
+    /// longest to check, slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<(&[String], Duration)> {
+        let mut timings: Vec<(&[String], Duration)> = self
+            .passed
+            .iter()
+            .map(|result| {
+                (
+                    result.combination.as_slice(),
+                    Duration::from_millis(result.elapsed_ms),
+                )
+            })
+            .chain(
+                self.failed
+                    .iter()
+                    .map(|fail| (fail.combination.as_slice(), fail.elapsed)),
+            )
+            .collect();
+        timings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        timings.truncate(count);
+        timings
+    }
+
+    /// Returns the combinations of `saved`, failed in an earlier run, that
+    /// this run didn't see pass, followed by those failing in this run.
+    pub fn still_failing(&self, saved: Vec<Vec<String>>) -> Vec<Vec<String>> {
+        let checked: Vec<&[String]> = self
+            .passed
+            .iter()
+            .map(|result| result.combination.as_slice())
+            .chain(self.failed.iter().map(|fail| fail.combination.as_slice()))
+            .collect();
+        saved
+            .into_iter()
+            .filter(|combo| !checked.contains(&combo.as_slice()))
+            .chain(self.failed.iter().map(|fail| fail.combination.clone()))
+            .collect()
+    }
+
+    /// Builds the serializable [`Report`] of this run, `elapsed` being the total run time.
+    pub fn to_report(&self, elapsed: Duration) -> Report {
+        let failed = |status| {
+            move |fail: &CheckError| {
+                let mut result = CheckResult::failed(
+                    fail.combination.clone(),
+                    fail.elapsed,
+                    fail.message.clone(),
+                    fail.stdout.clone(),
+                );
+                result.status = status;
+                result.minimal = fail.minimal.clone();
+                result.command = Some(fail.command.clone());
+                result.diagnostics = fail.diagnostics.clone();
+                result
+            }
+        };
+        let ignored = self.ignored.iter().map(failed(CheckStatus::Ignored));
+        let invalid = self.invalid.iter().map(failed(CheckStatus::Invalid));
+        let failed = self.failed.iter().map(failed(CheckStatus::Failed));
+        let skipped = self.skipped.iter().cloned().map(CheckResult::skipped);
+        let mut report = Report::new(
+            self.passed
+                .iter()
+                .cloned()
+                .chain(failed)
+                .chain(ignored)
+                .chain(invalid)
+                .chain(skipped)
+                .collect(),
+            elapsed,
+        );
+        report.interrupted = self.interrupted;
+        report.uncovered = self.uncovered.clone();
+        report
+    }
+}
+
+/// Machine-readable summary of a whole run, as written by `--output json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub passed: usize,
+    pub failed: usize,
+    #[serde(default)]
+    pub skipped: usize,
+    #[serde(default)]
+    pub ignored: usize,
+    #[serde(default)]
+    pub invalid: usize,
+    /// Wall-clock duration of the whole run, in milliseconds.
+    pub elapsed_ms: u64,
+    /// Whether the run was interrupted, `results` then only holds the checks that finished.
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Tested features that none of the combinations contains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uncovered: Vec<String>,
+    pub results: Vec<CheckResult>,
+}
+
+impl Report {
+    pub fn new(mut results: Vec<CheckResult>, elapsed: Duration) -> Self {
+        results.sort_by(|a, b| a.combination.cmp(&b.combination));
+        let count = |status| results.iter().filter(|r| r.status == status).count();
+        let failed = count(CheckStatus::Failed);
+        let skipped = count(CheckStatus::Skipped);
+        let ignored = count(CheckStatus::Ignored);
+        let invalid = count(CheckStatus::Invalid);
+        Self {
+            passed: results.len() - failed - skipped - ignored - invalid,
+            failed,
+            skipped,
+            ignored,
+            invalid,
+            elapsed_ms: elapsed.as_millis() as u64,
+            interrupted: false,
+            uncovered: Vec::new(),
+            results,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Report is always serializable")
+    }
+
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    /// Renders the report as a JUnit XML document with one `<testcase>` per
+    /// combination under a single `<testsuite>`.
+    pub fn to_junit(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuite name=\"multi-feature-check\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">\n",
+            self.results.len(),
+            self.failed + self.invalid,
+            self.skipped + self.ignored,
+            millis_to_secs(self.elapsed_ms)
+        ));
+        for result in &self.results {
+            let name = if result.combination.is_empty() {
+                "default".to_string()
+            } else {
+                result.combination.join(" ")
+            };
+            xml.push_str(&format!(
+                "  <testcase name=\"{}\" classname=\"multi-feature-check\" time=\"{}\"",
+                escape_xml(&name),
+                millis_to_secs(result.elapsed_ms)
+            ));
+            match &result.stderr {
+                Some(stderr)
+                    if matches!(result.status, CheckStatus::Failed | CheckStatus::Invalid) =>
+                {
+                    xml.push_str(">\n");
+                    let failed = result.command.as_deref().unwrap_or(&name);
+                    let reason = if result.status == CheckStatus::Invalid {
+                        "invalid combination"
+                    } else {
+                        "failed"
+                    };
+                    xml.push_str(&format!(
+                        "    <failure message=\"{} {}\">{}</failure>\n",
+                        escape_xml(failed),
+                        reason,
+                        escape_xml(stderr)
+                    ));
+                    if let Some(stdout) = &result.stdout {
+                        xml.push_str(&format!(
+                            "    <system-out>{}</system-out>\n",
+                            escape_xml(stdout)
+                        ));
+                    }
+                    xml.push_str("  </testcase>\n");
+                }
+                _ if result.status == CheckStatus::Skipped => xml.push_str(
+                    ">\n    <skipped message=\"superset of a failed combination\"/>\n  </testcase>\n",
+                ),
+                _ if result.status == CheckStatus::Ignored => xml.push_str(
+                    ">\n    <skipped message=\"ignored failure\"/>\n  </testcase>\n",
+                ),
+                _ => xml.push_str("/>\n"),
+            }
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+
+    pub fn write_junit(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_junit())
+    }
+
+    /// Renders the report as a TAP version 13 stream, with one test point per
+    /// combination and the captured output of failures as YAML diagnostics.
+    pub fn to_tap(&self) -> String {
+        let mut tap = format!("TAP version 13\n1..{}\n", self.results.len());
+        for (number, result) in self.results.iter().enumerate() {
+            let name = if result.combination.is_empty() {
+                "default features".to_string()
+            } else {
+                result.combination.join(" ")
+            };
+            let number = number + 1;
+            match result.status {
+                CheckStatus::Passed if result.cached => {
+                    tap.push_str(&format!("ok {} - {} # SKIP cached\n", number, name))
+                }
+                CheckStatus::Passed => tap.push_str(&format!("ok {} - {}\n", number, name)),
+                CheckStatus::Skipped => tap.push_str(&format!(
+                    "ok {} - {} # SKIP superset of a failed combination\n",
+                    number, name
+                )),
+                CheckStatus::Failed | CheckStatus::Ignored | CheckStatus::Invalid => {
+                    // TODO test points are expected to fail, they don't fail the run.
+                    let directive = match result.status {
+                        CheckStatus::Ignored => " # TODO ignored failure",
+                        CheckStatus::Invalid => " - invalid combination",
+                        _ => "",
+                    };
+                    tap.push_str(&format!("not ok {} - {}{}\n", number, name, directive));
+                    tap.push_str("  ---\n");
+                    tap.push_str(&format!("  duration_ms: {}\n", result.elapsed_ms));
+                    for (key, output) in [
+                        ("command", &result.command),
+                        ("stderr", &result.stderr),
+                        ("stdout", &result.stdout),
+                    ] {
+                        if let Some(output) = output.as_deref().filter(|o| !o.trim().is_empty()) {
+                            tap.push_str(&yaml_block(key, output));
+                        }
+                    }
+                    tap.push_str("  ...\n");
+                }
+            }
+        }
+        tap
+    }
+
+    /// Renders the report as a SARIF 2.1.0 log with one result per failed
+    /// combination, located at `manifest`, the URI of the checked
+    /// `Cargo.toml` as returned by [`sarif_uri`].
+    pub fn to_sarif(&self, manifest: &str) -> String {
+        let results: Vec<serde_json::Value> = self
+            .results
+            .iter()
+            .filter(|result| matches!(result.status, CheckStatus::Failed | CheckStatus::Invalid))
+            .map(|result| {
+                let name = if result.combination.is_empty() {
+                    "default features".to_string()
+                } else {
+                    result.combination.join(" ")
+                };
+                let stderr = strip_control(result.stderr.as_deref().unwrap_or_default());
+                let failed = match &result.command {
+                    Some(command) => format!("`{}` failed", command),
+                    None => "Build failed".to_string(),
+                };
+                serde_json::json!({
+                    "ruleId": SARIF_RULE_ID,
+                    "ruleIndex": 0,
+                    "level": "error",
+                    "message": {
+                        "text": format!("{} with features: {}\n\n{}", failed, name, stderr.trim_end()),
+                    },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": manifest },
+                            "region": { "startLine": 1 },
+                        },
+                    }],
+                    "partialFingerprints": { "featureCombination": name },
+                    "properties": {
+                        "combination": result.combination,
+                        "elapsedMs": result.elapsed_ms,
+                    },
+                })
+            })
+            .collect();
+        let sarif = serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": [{
+                            "id": SARIF_RULE_ID,
+                            "name": "FeatureComboBuildFailure",
+                            "shortDescription": {
+                                "text": "A feature combination fails to build",
+                            },
+                            "defaultConfiguration": { "level": "error" },
+                        }],
+                    },
+                },
+                "results": results,
+            }],
+        });
+        serde_json::to_string_pretty(&sarif).expect("SARIF log is always serializable")
+    }
+}
+
+/// Rule of the SARIF results, every failed combination being one.
+const SARIF_RULE_ID: &str = "feature-combo-build-failure";
+
+/// Returns the SARIF artifact URI of `path`, with forward slashes: relative
+/// to the current directory when under it, else a `file://` URI of the
+/// absolute path.
+pub fn sarif_uri(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok());
+    let uri = relative
+        .unwrap_or(path)
+        .components()
+        .filter(|component| {
+            !matches!(
+                component,
+                std::path::Component::CurDir | std::path::Component::RootDir
+            )
+        })
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if relative.is_none() && path.is_absolute() {
+        format!("file:///{}", uri)
+    } else {
+        uri
+    }
+}
+
+/// Drops control characters such as color codes, keeping line breaks and tabs.
+fn strip_control(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .collect()
+}
+
+/// Renders `value` as a literal block scalar under `key`, in a YAML document
+/// indented by two spaces. The indentation is explicit since cargo's output
+/// often starts with indented lines, control characters such as color codes
+/// are dropped as YAML doesn't allow them.
+fn yaml_block(key: &str, value: &str) -> String {
+    let mut yaml = format!("  {}: |2-\n", key);
+    for line in value.trim_end().lines() {
+        let line: String = line
+            .chars()
+            .filter(|c| !c.is_control() || *c == '\t')
+            .collect();
+        if !line.is_empty() {
+            yaml.push_str("    ");
+            yaml.push_str(&line);
+        }
+        yaml.push('\n');
+    }
+    yaml
+}
+
+fn millis_to_secs(millis: u64) -> String {
+    format!("{:.3}", millis as f64 / 1000.0)
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not valid XML 1.0.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format of the final summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+    Tap,
+    Sarif,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "human" => Some(OutputFormat::Human),
+            "json" => Some(OutputFormat::Json),
+            "tap" => Some(OutputFormat::Tap),
+            "sarif" => Some(OutputFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_json_round_trips() {
+        let report = Report::new(
+            vec![
+                CheckResult::failed(
+                    vec!["b".to_string()],
+                    Duration::from_millis(20),
+                    "error[E0425]".to_string(),
+                    "build script output".to_string(),
+                ),
+                CheckResult::passed(vec!["a".to_string()], Duration::from_millis(10)),
+            ],
+            Duration::from_secs(1),
+        );
+
+        let parsed: Report = serde_json::from_str(&report.to_json()).unwrap();
+
+        assert_eq!(parsed, report);
+        assert_eq!(parsed.passed, 1);
+        assert_eq!(parsed.failed, 1);
+        assert_eq!(parsed.results[0].combination, vec!["a"]);
+        assert_eq!(parsed.results[0].stderr, None);
+        assert_eq!(parsed.results[1].stderr.as_deref(), Some("error[E0425]"));
+        assert_eq!(
+            parsed.results[1].stdout.as_deref(),
+            Some("build script output")
+        );
+    }
+
+    #[test]
+    fn still_failing_drops_the_combinations_that_pass() {
+        let failed = |combo: &[&str]| CheckError {
+            combination: combo.iter().map(|f| f.to_string()).collect(),
+            command: String::new(),
+            message: String::new(),
+            stdout: String::new(),
+            diagnostics: Vec::new(),
+            elapsed: Duration::ZERO,
+            minimal: None,
+        };
+        let report = RunReport {
+            passed: vec![CheckResult::passed(vec!["a".to_string()], Duration::ZERO)],
+            failed: vec![failed(&["b"]), failed(&["d"])],
+            ..RunReport::default()
+        };
+        let saved = vec![
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+        ];
+
+        assert_eq!(
+            report.still_failing(saved),
+            vec![
+                vec!["c".to_string()],
+                vec!["b".to_string()],
+                vec!["d".to_string()]
+            ]
+        );
+    }
+
+    #[test]
+    fn slowest_orders_passed_and_failed_by_duration() {
+        let report = RunReport {
+            passed: vec![
+                CheckResult::passed(vec!["a".to_string()], Duration::from_millis(10)),
+                CheckResult::passed(vec!["b".to_string()], Duration::from_millis(30)),
+            ],
+            failed: vec![CheckError {
+                combination: vec!["c".to_string()],
+                command: "cargo check --no-default-features --features c".to_string(),
+                message: String::new(),
+                stdout: String::new(),
+                diagnostics: Vec::new(),
+                elapsed: Duration::from_millis(20),
+                minimal: None,
+            }],
+            ignored: Vec::new(),
+            invalid: Vec::new(),
+            skipped: Vec::new(),
+            stopped_early: false,
+            interrupted: false,
+            uncovered: Vec::new(),
+        };
+
+        let slowest: Vec<_> = report
+            .slowest(2)
+            .into_iter()
+            .map(|(combo, elapsed)| (combo.join(" "), elapsed.as_millis()))
+            .collect();
+
+        assert_eq!(slowest, vec![("b".to_string(), 30), ("c".to_string(), 20)]);
+    }
+
+    #[test]
+    fn failures_are_grouped_by_message() {
+        let fail = |combo: &str, message: &str| CheckError {
+            combination: vec![combo.to_string()],
+            command: format!("cargo check --features {}", combo),
+            message: message.to_string(),
+            stdout: String::new(),
+            diagnostics: Vec::new(),
+            elapsed: Duration::ZERO,
+            minimal: None,
+        };
+        let failed = vec![
+            fail("a", "error: broken\n  at lib.rs"),
+            fail("b", "error: other"),
+            fail("c", "error:  broken\n\tat lib.rs\n"),
+        ];
+
+        let groups: Vec<Vec<&str>> = group_by_message(&failed)
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|fail| fail.combination[0].as_str())
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(groups, vec![vec!["a", "c"], vec!["b"]]);
+    }
+
+    #[test]
+    fn ignored_and_invalid_failures_are_reported_apart() {
+        let fail = |combo: &str| CheckError {
+            combination: vec![combo.to_string()],
+            command: format!("cargo check --features {}", combo),
+            message: "error: broken".to_string(),
+            stdout: String::new(),
+            diagnostics: Vec::new(),
+            elapsed: Duration::ZERO,
+            minimal: None,
+        };
+        let run_report = RunReport {
+            failed: vec![fail("a")],
+            ignored: vec![fail("b")],
+            invalid: vec![fail("c")],
+            ..RunReport::default()
+        };
+
+        let report = run_report.to_report(Duration::from_secs(1));
+
+        assert_eq!(
+            (report.passed, report.failed, report.ignored, report.invalid),
+            (0, 1, 1, 1)
+        );
+        assert_eq!(report.results[1].status, CheckStatus::Ignored);
+        assert_eq!(report.results[2].status, CheckStatus::Invalid);
+        let tap = report.to_tap();
+        assert!(tap.contains("not ok 2 - b # TODO ignored failure\n"));
+        assert!(tap.contains("not ok 3 - c - invalid combination\n"));
+        let junit = report.to_junit();
+        assert!(junit.contains("<skipped message=\"ignored failure\"/>"));
+        assert!(junit.contains("failures=\"2\""));
+        assert!(junit.contains("--features c invalid combination\">"));
+    }
+
+    #[test]
+    fn junit_escapes_names_and_messages() {
+        let report = Report::new(
+            vec![
+                CheckResult::passed(vec![], Duration::from_millis(1500)),
+                CheckResult::failed(
+                    vec!["a\"b".to_string(), "c<d>".to_string()],
+                    Duration::from_millis(20),
+                    "expected `&str`, found 'x'\u{1b}[0m".to_string(),
+                    String::new(),
+                ),
+            ],
+            Duration::from_secs(2),
+        );
+
+        let xml = report.to_junit();
+
+        assert!(xml.contains("tests=\"2\" failures=\"1\" skipped=\"0\" time=\"2.000\""));
+        assert!(xml.contains(
+            "<testcase name=\"default\" classname=\"multi-feature-check\" time=\"1.500\"/>"
+        ));
+        assert!(xml.contains("name=\"a&quot;b c&lt;d&gt;\""));
+        assert!(xml.contains(">expected `&amp;str`, found &apos;x&apos;[0m</failure>"));
+    }
+
+    #[test]
+    fn tap_reports_each_combination() {
+        let report = Report::new(
+            vec![
+                CheckResult::passed(Vec::new(), Duration::from_millis(10)),
+                CheckResult::failed(
+                    vec!["b".to_string()],
+                    Duration::from_millis(20),
+                    "    Checking x\nerror: broken\n\n\u{1b}[0mdone\n".to_string(),
+                    String::new(),
+                ),
+                CheckResult::skipped(vec!["b".to_string(), "c".to_string()]),
+            ],
+            Duration::from_secs(1),
+        );
+
+        let expected = [
+            "TAP version 13",
+            "1..3",
+            "ok 1 - default features",
+            "not ok 2 - b",
+            "  ---",
+            "  duration_ms: 20",
+            "  stderr: |2-",
+            "        Checking x",
+            "    error: broken",
+            "",
+            "    [0mdone",
+            "  ...",
+            "ok 3 - b c # SKIP superset of a failed combination",
+        ];
+        assert_eq!(report.to_tap(), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn cached_combinations_count_as_passed() {
+        let report = Report::new(
+            vec![
+                CheckResult::passed(Vec::new(), Duration::from_millis(10)),
+                CheckResult::cached(vec!["a".to_string()]),
+            ],
+            Duration::from_secs(1),
+        );
+
+        assert_eq!(report.passed, 2);
+        let expected = [
+            "TAP version 13",
+            "1..2",
+            "ok 1 - default features",
+            "ok 2 - a # SKIP cached",
+        ];
+        assert_eq!(report.to_tap(), expected.join("\n") + "\n");
+        let parsed: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(parsed["results"][0].get("cached"), None);
+        assert_eq!(parsed["results"][1]["cached"], true);
+    }
+
+    #[test]
+    fn sarif_reports_failed_combinations() {
+        let report = Report::new(
+            vec![
+                CheckResult::passed(Vec::new(), Duration::from_millis(10)),
+                CheckResult::failed(
+                    vec!["a".to_string(), "b".to_string()],
+                    Duration::from_millis(20),
+                    "\u{1b}[0merror: broken\n".to_string(),
+                    String::new(),
+                ),
+            ],
+            Duration::from_secs(1),
+        );
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&report.to_sarif("crates/x/Cargo.toml")).unwrap();
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], SARIF_RULE_ID);
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["ruleId"], SARIF_RULE_ID);
+        assert_eq!(
+            results[0]["message"]["text"],
+            "Build failed with features: a b\n\n[0merror: broken"
+        );
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "crates/x/Cargo.toml"
+        );
+    }
+
+    #[test]
+    fn sarif_uri_is_relative_with_forward_slashes() {
+        assert_eq!(sarif_uri(Path::new("./x/Cargo.toml")), "x/Cargo.toml");
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(sarif_uri(&cwd.join("Cargo.toml")), "Cargo.toml");
+        let outside = cwd.parent().unwrap().join("elsewhere").join("Cargo.toml");
+        let uri = sarif_uri(&outside);
+        assert!(uri.starts_with("file:///"), "{}", uri);
+        assert!(uri.ends_with("/elsewhere/Cargo.toml"), "{}", uri);
+        assert!(!uri.starts_with("file:////"), "{}", uri);
+    }
+}
@@ -0,0 +1,1390 @@
+use crate::combinations::{ALL_FEATURES, NO_DEFAULT_FEATURES};
+use crate::config::{CheckCommand, GlobalConfig, Warmup};
+use crate::diagnostics::{parse_messages, render_stderr, Diagnostic, Level, MESSAGE_FORMAT_ARG};
+use crate::error::MultiCheckError;
+use crate::logs::write_log;
+use crate::progress::{
+    bar_style, format_eta, interactive, plain_progress, print_periodically, progress_template,
+    spinner_style, spinner_template, truncate_message, Eta, SPINNER_MESSAGE_WIDTH,
+};
+use crate::project::RustProject;
+use crate::report::{CheckError, CheckResult, RunReport};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, io};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::task::JoinSet;
+use tracing::debug;
+
+/// Checks every combination of `project` according to `configs`, running at
+/// most `configs.concurrency` cargo processes at once. Combinations are only
+/// pulled from `combinations` as workers free up, `total` is their number as
+/// shown on the progress bar.
+///
+/// With `prune_supersets`, combinations should come smallest first so that
+/// failures are known before their supersets are checked.
+pub async fn run_combinations<I>(
+    configs: &GlobalConfig,
+    project: &RustProject,
+    combinations: I,
+    total: u64,
+) -> RunReport
+where
+    I: IntoIterator<Item = Vec<String>>,
+{
+    // In verbose mode cargo writes straight to the terminal, drawing the
+    // spinners on top of it would garble the output.
+    let interactive = interactive(configs);
+    let multi_progress = if configs.verbose || !interactive {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+    let mut progresses = vec![];
+
+    // Add spinners
+    // Config::new never leaves it at 0, but a zero-sized pool would hang forever.
+    let concurrency = configs.concurrency.max(1);
+    for i in 0..concurrency {
+        let spinner = multi_progress.add(ProgressBar::new_spinner());
+        spinner.set_style(spinner_style(configs, spinner_template(configs)));
+        spinner.set_prefix(format!("{}/{}", i + 1, concurrency));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        progresses.push((i, spinner));
+    }
+
+    let global_progress = multi_progress.add(ProgressBar::new(total));
+    global_progress.enable_steady_tick(Duration::from_millis(100));
+    global_progress.set_style(bar_style(configs, progress_template(configs)));
+    global_progress.set_message(format_eta(None));
+    let eta = Arc::new(Mutex::new(Eta::default()));
+
+    // Without bars, CI logs still get to see the run moving.
+    let plain_progress_task = (!interactive && !configs.quiet)
+        .then(|| print_periodically(global_progress.clone(), "Tested"));
+
+    // Limit the number of cargo processes running at once, each running check
+    // borrows an idle worker slot (and its spinner) for the time it holds its permit.
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    let mut report = RunReport::default();
+    let idle_spinners = Arc::new(Mutex::new(progresses));
+    // Flipped to true in fail-fast mode once a check fails, pending checks are
+    // skipped and running ones are dropped, which kills their cargo process.
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let cancel_tx = Arc::new(cancel_tx);
+    // Ctrl-C cancels the run the same way, so that every cargo child is killed
+    // and the results gathered so far can still be reported.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let ctrl_c = {
+        let cancel_tx = cancel_tx.clone();
+        let interrupted = interrupted.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                interrupted.store(true, Ordering::SeqCst);
+                cancel_tx.send_replace(true);
+            }
+        })
+    };
+    // Failures are printed as soon as they happen, above the bars, so that
+    // they aren't lost if the run gets killed.
+    let (failures_tx, mut failures_rx) = mpsc::unbounded_channel::<String>();
+    let reporter = {
+        let multi_progress = multi_progress.clone();
+        tokio::spawn(async move {
+            while let Some(line) = failures_rx.recv().await {
+                multi_progress.suspend(|| println!("{}", line));
+            }
+        })
+    };
+    // Failed combinations, whose supersets are skipped when pruning.
+    let known_failures: Arc<Mutex<Vec<Vec<String>>>> = Arc::default();
+    for combo in combinations {
+        if *cancel_rx.borrow() {
+            break;
+        }
+        let known_failures = known_failures.clone();
+        let eta = eta.clone();
+        let path_clone = project.path.clone();
+        let configs = configs.clone();
+        let spinners = idle_spinners.clone();
+        let gl_pb = global_progress.clone();
+        let cancel_tx = cancel_tx.clone();
+        let mut cancel_rx = cancel_rx.clone();
+        let failures_tx = failures_tx.clone();
+        let task = async move {
+            if *cancel_rx.borrow() {
+                return Outcome::Cancelled;
+            }
+            if configs.prune_supersets
+                && is_superset_of_any(&combo, &known_failures.lock().unwrap())
+            {
+                gl_pb.inc(1);
+                return Outcome::Skipped(combo);
+            }
+            let (worker, pb) = spinners.lock().unwrap().pop().unwrap();
+            let target_dir = configs
+                .isolated_target_dirs
+                .then(|| worker_target_dir(&path_clone, worker));
+            let check = run_commands(&configs, &path_clone, &combo, target_dir.as_deref(), &pb);
+            let runs = tokio::select! {
+                runs = check => Some(runs),
+                _ = cancel_rx.wait_for(|cancelled| *cancelled) => None,
+            };
+            // Counted once, however many attempts it took.
+            if let Some(runs) = &runs {
+                gl_pb.inc(1);
+                let mut eta = eta.lock().unwrap();
+                eta.record(runs.iter().map(|run| run.elapsed).sum());
+                let remaining = gl_pb.length().unwrap_or(0).saturating_sub(gl_pb.position());
+                gl_pb.set_message(format_eta(eta.remaining(remaining, configs.concurrency)));
+            }
+            spinners.lock().unwrap().push((worker, pb));
+            if runs.as_deref().is_some_and(|runs| !passed(runs)) {
+                if configs.fail_fast && !configs.is_ignored(&combo) {
+                    cancel_tx.send_replace(true);
+                }
+                if configs.prune_supersets {
+                    known_failures.lock().unwrap().push(combo.clone());
+                }
+            }
+            if let Some(Err(output)) = runs
+                .as_deref()
+                .and_then(|runs| runs.last())
+                .map(|run| &run.result)
+            {
+                if !configs.quiet {
+                    // The reporter only stops once every sender is dropped.
+                    let _ = failures_tx.send(failure_line(&configs, &combo, output));
+                }
+            }
+            if let (Some(dir), Some(runs)) = (&configs.log_dir, &runs) {
+                for run in runs {
+                    let (Ok(output) | Err(output)) = &run.result;
+                    let command = format_env_command(&run.env, &run.args);
+                    if let Err(err) = write_log(
+                        dir,
+                        &combo,
+                        &command,
+                        run.result.is_ok(),
+                        run.elapsed,
+                        &output.stdout,
+                        &output.stderr,
+                    ) {
+                        eprintln!("Failed to write the log of {}: {}", command, err);
+                    }
+                }
+            }
+            match runs {
+                Some(runs) => Outcome::Checked(combo, runs),
+                None => Outcome::Cancelled,
+            }
+        };
+        spawn_with_permit(&semaphore, &mut tasks, task).await;
+        // Collect what already finished, so that results don't pile up in the set.
+        while let Some(outcome) = tasks.try_join_next() {
+            outcome.unwrap().record(&mut report);
+        }
+    }
+    while let Some(outcome) = tasks.join_next().await {
+        outcome.unwrap().record(&mut report);
+    }
+    // Checks complete in any order.
+    report
+        .passed
+        .sort_by(|a, b| a.combination.cmp(&b.combination));
+    report
+        .failed
+        .sort_by(|a, b| a.combination.cmp(&b.combination));
+    report
+        .invalid
+        .sort_by(|a, b| a.combination.cmp(&b.combination));
+    report.skipped.sort();
+    let (ignored, failed) = report
+        .failed
+        .into_iter()
+        .partition(|fail| configs.is_ignored(&fail.combination));
+    report.failed = failed;
+    report.ignored = ignored;
+
+    drop(failures_tx);
+    reporter.await.unwrap();
+    ctrl_c.abort();
+    if let Some(task) = plain_progress_task {
+        task.abort();
+        println!("{}", plain_progress(&global_progress, "Tested"));
+    }
+    multi_progress.clear().unwrap();
+    report.interrupted = interrupted.load(Ordering::SeqCst);
+    report.stopped_early = !report.interrupted && *cancel_rx.borrow();
+    report
+}
+
+/// What became of a single combination in [`run_combinations`].
+enum Outcome {
+    /// Cargo ran each of the given commands.
+    Checked(Vec<String>, Vec<CommandRun>),
+    /// Not run, being a superset of a combination that already failed.
+    Skipped(Vec<String>),
+    /// Not run or killed, after fail-fast or Ctrl-C.
+    Cancelled,
+}
+
+impl Outcome {
+    /// Adds this outcome to `report`.
+    fn record(self, report: &mut RunReport) {
+        match self {
+            Outcome::Checked(combination, mut runs) => {
+                let elapsed = runs.iter().map(|run| run.elapsed).sum();
+                // Commands stop at the first failure, which is the last run.
+                let last = runs.pop().expect("at least one command is run");
+                match last.result {
+                    Ok(_) => report
+                        .passed
+                        .push(CheckResult::passed(combination, elapsed)),
+                    Err(output) => {
+                        let failures = if output.invalid {
+                            &mut report.invalid
+                        } else {
+                            &mut report.failed
+                        };
+                        failures.push(CheckError {
+                            combination,
+                            command: format_env_command(&last.env, &last.args),
+                            message: output.stderr,
+                            stdout: output.stdout,
+                            diagnostics: output.diagnostics,
+                            elapsed,
+                            minimal: None,
+                        })
+                    }
+                }
+            }
+            Outcome::Skipped(combination) => report.skipped.push(combination),
+            Outcome::Cancelled => {}
+        }
+    }
+}
+
+/// Returns the line reporting the failure of `combo` as soon as it happens,
+/// with its first compiler error, else the first error line of its output.
+fn failure_line(configs: &GlobalConfig, combo: &[String], output: &CheckOutput) -> String {
+    let color = configs.color.enabled_on_stdout();
+    let lines = || output.stderr.lines().map(str::trim);
+    let error = output
+        .diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.level == Level::Error)
+        .map(|diagnostic| diagnostic.headline(color))
+        .or_else(|| {
+            lines()
+                .find(|line| line.starts_with("error"))
+                .map(str::to_string)
+        })
+        .or_else(|| lines().find(|line| !line.is_empty()).map(str::to_string))
+        .unwrap_or_default();
+    let failed = if output.invalid {
+        "Invalid combination"
+    } else if configs.is_ignored(combo) {
+        "Ignored failure of"
+    } else {
+        "Failed"
+    };
+    format!("{} {:?}: {}", failed, combo.join(" "), error)
+}
+
+/// Returns whether `combo` contains every feature of one of the `failures`.
+fn is_superset_of_any(combo: &[String], failures: &[Vec<String>]) -> bool {
+    failures
+        .iter()
+        .any(|failed| !failed.is_empty() && failed.iter().all(|feature| combo.contains(feature)))
+}
+
+/// A cargo command run on a combination, with its outcome.
+struct CommandRun {
+    args: Vec<String>,
+    /// Environment variables set on cargo, see [`check_env`].
+    env: BTreeMap<String, String>,
+    result: Result<CheckOutput, CheckOutput>,
+    /// Time spent in cargo, over every attempt.
+    elapsed: Duration,
+}
+
+/// Returns whether every command of `runs` passed.
+fn passed(runs: &[CommandRun]) -> bool {
+    runs.iter().all(|run| run.result.is_ok())
+}
+
+/// Runs each of the configured commands on `combo` in order, retrying them
+/// as configured and stopping at the first one that fails. With
+/// `target_dir`, cargo builds in that directory.
+async fn run_commands(
+    configs: &GlobalConfig,
+    path: &Path,
+    combo: &[String],
+    target_dir: Option<&Path>,
+    pb: &ProgressBar,
+) -> Vec<CommandRun> {
+    let mut runs = Vec::new();
+    for command in configs.commands() {
+        let mut args = check_args(configs, command, combo);
+        if let Some(target_dir) = target_dir {
+            insert_before_separator(
+                &mut args,
+                ["--target-dir".to_string(), target_dir.display().to_string()],
+            );
+        }
+        let args = run_args(configs, args);
+        let env = check_env(configs, command);
+        let (result, elapsed) = retry(configs.retries, || {
+            make_checks(&env, &args, path, configs, pb)
+        })
+        .await;
+        let failed = result.is_err();
+        runs.push(CommandRun {
+            args,
+            env,
+            result,
+            elapsed,
+        });
+        if failed {
+            break;
+        }
+    }
+    runs
+}
+
+/// Builds the cargo arguments used to check `combo` with `command`. The
+/// documentation is only built for the checked crate, not its dependencies.
+pub fn check_args(configs: &GlobalConfig, command: CheckCommand, combo: &[String]) -> Vec<String> {
+    let mut args = toolchain_args(configs);
+    args.push(command.as_str().to_string());
+    if command == CheckCommand::Doc {
+        args.push("--no-deps".to_string());
+    }
+    args.extend(package_args(configs));
+    if combo == [ALL_FEATURES] {
+        args.push(ALL_FEATURES.to_string());
+    } else if combo == [NO_DEFAULT_FEATURES] {
+        args.push(NO_DEFAULT_FEATURES.to_string());
+    } else if !combo.is_empty() {
+        if !configs.keep_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args.push("--features".to_string());
+        args.push(combo.join(" "));
+    }
+    args.extend(profile_args(configs));
+    if let Some(target) = &configs.target {
+        args.push("--target".to_string());
+        args.push(target.clone());
+    }
+    if configs.locked {
+        args.push("--locked".to_string());
+    }
+    if configs.offline {
+        args.push("--offline".to_string());
+    }
+    args.extend(configs.extra_cargo_args.iter().cloned());
+    insert_before_separator(&mut args, feature_args(configs, combo));
+    args
+}
+
+/// Returns the `cargo_args` of the features of `combo`, of every feature for
+/// `--all-features`, in the order of the feature names.
+fn feature_args(configs: &GlobalConfig, combo: &[String]) -> Vec<String> {
+    let all_features = combo == [ALL_FEATURES];
+    configs
+        .feature_cargo_args
+        .iter()
+        .filter(|(feature, _)| all_features || combo.contains(feature))
+        .flat_map(|(_, args)| args.iter().cloned())
+        .collect()
+}
+
+/// Returns the arguments selecting the build profile: `--release` for the
+/// `release` profile, `--profile <name>` for the others.
+fn profile_args(configs: &GlobalConfig) -> Vec<String> {
+    match configs.profile.as_deref() {
+        Some("release") => vec!["--release".to_string()],
+        Some(profile) => vec!["--profile".to_string(), profile.to_string()],
+        None => Vec::new(),
+    }
+}
+
+/// Returns the `--manifest-path <path>` and `-p <package>` arguments
+/// selecting the checked crate, if any.
+fn package_args(configs: &GlobalConfig) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(manifest) = &configs.manifest_path {
+        args.push("--manifest-path".to_string());
+        args.push(manifest.display().to_string());
+    }
+    if let Some(package) = &configs.package {
+        args.push("-p".to_string());
+        args.push(package.clone());
+    }
+    args
+}
+
+/// Returns the target directory reserved for the worker slot `worker` when
+/// `isolated_target_dirs` is enabled.
+pub fn worker_target_dir(project_dir: &Path, worker: usize) -> PathBuf {
+    project_dir
+        .join("target")
+        .join("multi-check")
+        .join(format!("worker-{}", worker + 1))
+}
+
+/// Inserts `extra` before a `--` separator in `args`, so that cargo rather
+/// than the underlying tool receives them, or at the end if there is none.
+fn insert_before_separator(args: &mut Vec<String>, extra: impl IntoIterator<Item = String>) {
+    let pos = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    args.splice(pos..pos, extra);
+}
+
+/// Returns the cargo binary to run: `cargo_bin`, else the `CARGO` environment
+/// variable set when running as a cargo subcommand, else `cargo` from `PATH`.
+pub fn cargo_bin(configs: &GlobalConfig) -> String {
+    if let Some(bin) = &configs.cargo_bin {
+        return bin.clone();
+    }
+    // `CARGO` points at the toolchain's own cargo rather than the rustup
+    // proxy, which wouldn't understand `+toolchain`.
+    match env::var("CARGO") {
+        Ok(bin) if !bin.is_empty() && configs.toolchain.is_none() => bin,
+        _ => "cargo".to_string(),
+    }
+}
+
+/// Returns a command running cargo, with the environment variables of `configs.env`.
+fn cargo_command(configs: &GlobalConfig) -> Command {
+    let mut command = Command::new(cargo_bin(configs));
+    command.envs(cargo_env(configs, &[], |name| env::var(name).ok()));
+    command
+}
+
+/// Environment variables set on the cargo processes running `command`, see
+/// [`cargo_env`]. Rustdoc warnings, such as broken intra-doc links, always
+/// fail the `doc` checks.
+pub fn check_env(configs: &GlobalConfig, command: CheckCommand) -> BTreeMap<String, String> {
+    let denied: &[&str] = if configs.deny_warnings {
+        &FLAGS_VARIABLES
+    } else if command == CheckCommand::Doc {
+        &["RUSTDOCFLAGS"]
+    } else {
+        &[]
+    };
+    cargo_env(configs, denied, |name| env::var(name).ok())
+}
+
+/// Flag variables that are merged rather than replaced.
+const FLAGS_VARIABLES: [&str; 2] = ["RUSTFLAGS", "RUSTDOCFLAGS"];
+
+/// Builds the environment variables set on cargo processes: those of
+/// `configs.env`, except for [`FLAGS_VARIABLES`] which are appended to the
+/// value inherited from the parent (read with `parent`), along with
+/// `-D warnings` for those listed in `denied`. The warm-up build doesn't deny
+/// warnings, they must not fail it.
+fn cargo_env(
+    configs: &GlobalConfig,
+    denied: &[&str],
+    parent: impl Fn(&str) -> Option<String>,
+) -> BTreeMap<String, String> {
+    let mut vars: BTreeMap<String, String> = configs
+        .env
+        .iter()
+        .filter(|(name, _)| !FLAGS_VARIABLES.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    for name in FLAGS_VARIABLES {
+        let additions: Vec<String> = [
+            configs.env.get(name).cloned(),
+            denied.contains(&name).then(|| "-D warnings".to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        // Left alone when there is nothing to add, cargo would otherwise
+        // ignore `build.rustflags` from its configuration.
+        if additions.is_empty() {
+            continue;
+        }
+        let flags: Vec<String> = parent(name)
+            .into_iter()
+            .chain(additions)
+            .map(|flags| flags.trim().to_string())
+            .filter(|flags| !flags.is_empty())
+            .collect();
+        vars.insert(name.to_string(), flags.join(" "));
+    }
+    vars
+}
+
+/// Describes a failure to start cargo, spelling out a missing binary.
+fn spawn_error(configs: &GlobalConfig, err: io::Error) -> io::Error {
+    if err.kind() == io::ErrorKind::NotFound {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("cargo binary not found: {}", cargo_bin(configs)),
+        )
+    } else {
+        io::Error::new(err.kind(), format!("Failed to run cargo: {}", err))
+    }
+}
+
+/// Returns the `+toolchain` argument that must come first on the cargo command line, if any.
+pub fn toolchain_args(configs: &GlobalConfig) -> Vec<String> {
+    configs
+        .toolchain
+        .iter()
+        .map(|toolchain| format!("+{}", toolchain.trim_start_matches('+')))
+        .collect()
+}
+
+/// Formats a cargo invocation for display, quoting arguments containing spaces.
+pub fn format_command(args: &[String]) -> String {
+    format_env_command(&BTreeMap::new(), args)
+}
+
+/// Formats a cargo invocation for display like [`format_command`], preceded
+/// by the variables of `env` as a shell would set them, so that the command
+/// reproduces the check.
+fn format_env_command(env: &BTreeMap<String, String>, args: &[String]) -> String {
+    let quote = |value: &str| {
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            format!("\"{}\"", value)
+        } else {
+            value.to_string()
+        }
+    };
+    let mut command = String::new();
+    for (name, value) in env {
+        command.push_str(&format!("{}={} ", name, quote(value)));
+    }
+    command.push_str("cargo");
+    for arg in args {
+        command.push(' ');
+        command.push_str(&quote(arg));
+    }
+    command
+}
+
+/// Shrinks the failing `combination` to a 1-minimal subset that still fails,
+/// with a spinner on `pb`. Each check run is deducted from `budget`, the
+/// smallest failing subset found so far is returned once it runs out.
+pub async fn minimize_combination(
+    configs: &GlobalConfig,
+    project: &RustProject,
+    combination: &[String],
+    budget: &mut usize,
+    pb: &ProgressBar,
+) -> Vec<String> {
+    delta_debug(combination.to_vec(), budget, |subset| async move {
+        let runs = run_commands(configs, &project.path, &subset, None, pb).await;
+        !passed(&runs)
+    })
+    .await
+}
+
+/// Delta debugging: looks for a subset of `items` for which `fails` still
+/// returns true by removing ever smaller chunks of it, until no single item
+/// can be removed or `budget` runs out. Empty subsets are never tried since
+/// they would check the default features instead.
+async fn delta_debug<F, Fut>(
+    mut items: Vec<String>,
+    budget: &mut usize,
+    mut fails: F,
+) -> Vec<String>
+where
+    F: FnMut(Vec<String>) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut chunks = 2;
+    while items.len() > 1 {
+        let chunk_size = items.len().div_ceil(chunks);
+        let mut reduced = false;
+        for start in (0..items.len()).step_by(chunk_size) {
+            if *budget == 0 {
+                return items;
+            }
+            *budget -= 1;
+            let complement: Vec<String> = items[..start]
+                .iter()
+                .chain(items.iter().skip(start + chunk_size))
+                .cloned()
+                .collect();
+            if fails(complement.clone()).await {
+                items = complement;
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if chunks >= items.len() {
+                break;
+            }
+            chunks = (chunks * 2).min(items.len());
+        }
+    }
+    items
+}
+
+/// Runs `check` again while it fails, up to `retries` more times. Returns the
+/// last result along with the time spent in every attempt.
+async fn retry<F, Fut, T, E>(retries: usize, mut check: F) -> (Result<T, E>, Duration)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = (Result<T, E>, Duration)>,
+{
+    let mut total = Duration::ZERO;
+    let mut attempt = 0;
+    loop {
+        let (result, elapsed) = check().await;
+        total += elapsed;
+        if result.is_ok() || attempt == retries {
+            return (result, total);
+        }
+        attempt += 1;
+    }
+}
+
+/// Captured output of a check, empty in verbose mode.
+#[derive(Debug)]
+struct CheckOutput {
+    /// Cargo's stderr, with the compiler messages rendered as usual.
+    stderr: String,
+    /// Cargo's stdout, without the JSON compiler messages.
+    stdout: String,
+    diagnostics: Vec<Diagnostic>,
+    /// Whether cargo rejected the combination itself, see [`is_unknown_feature_error`].
+    invalid: bool,
+}
+
+impl CheckOutput {
+    fn new(stderr: &[u8], stdout: &[u8]) -> Self {
+        let (diagnostics, stdout) = parse_messages(&String::from_utf8_lossy(stdout));
+        Self {
+            stderr: render_stderr(&String::from_utf8_lossy(stderr), &diagnostics),
+            stdout,
+            diagnostics,
+            invalid: false,
+        }
+    }
+
+    /// Output of a check that couldn't run to completion.
+    fn error(message: String) -> Self {
+        Self {
+            stderr: message,
+            stdout: String::new(),
+            diagnostics: Vec::new(),
+            invalid: false,
+        }
+    }
+}
+
+/// Returns whether `stderr` is cargo refusing to resolve features the package
+/// doesn't have, rather than a build failure. Cargo words it differently
+/// across versions and whether `--package` is passed.
+fn is_unknown_feature_error(stderr: &str) -> bool {
+    const PATTERNS: [&str; 4] = [
+        "none of the selected packages contains",
+        "does not contain this feature",
+        "does not contain these features",
+        "does not have the feature",
+    ];
+    stderr.lines().any(|line| {
+        line.starts_with("error") && PATTERNS.iter().any(|pattern| line.contains(pattern))
+    })
+}
+
+/// Completes `args`, as built by [`check_args`], into the arguments cargo is
+/// run with: JSON compiler messages to parse, unless in verbose mode, and the
+/// jobs of each check.
+fn run_args(configs: &GlobalConfig, mut args: Vec<String>) -> Vec<String> {
+    if !configs.verbose && !args.iter().any(|arg| arg.starts_with("--message-format")) {
+        insert_before_separator(&mut args, [MESSAGE_FORMAT_ARG.to_string()]);
+    }
+    // Checks running side by side share the CPUs rather than each using all
+    // of them, unless the extra arguments set the jobs themselves.
+    if !args
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg.starts_with("-j") || arg.starts_with("--jobs"))
+    {
+        let jobs = configs.cargo_jobs().to_string();
+        insert_before_separator(&mut args, ["--jobs".to_string(), jobs]);
+    }
+    args
+}
+
+/// Runs cargo with `args`, as built by [`run_args`], and the variables of
+/// `env`, returning the outcome along with the time spent in the cargo process.
+async fn make_checks(
+    env: &BTreeMap<String, String>,
+    args: &[String],
+    path: &Path,
+    configs: &GlobalConfig,
+    check_pb: &ProgressBar,
+) -> (Result<CheckOutput, CheckOutput>, Duration) {
+    // The full command is printed in verbose mode and with failures.
+    check_pb.set_message(truncate_message(
+        &format!("Running {}", format_command(args)),
+        SPINNER_MESSAGE_WIDTH,
+    ));
+
+    let mut command = Command::new(cargo_bin(configs));
+    command.current_dir(path).envs(env).args(args);
+    if configs.verbose {
+        println!("Running {}", format_env_command(env, args));
+        command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+    } else {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    let timeout = configs.timeout_secs.map(Duration::from_secs);
+    debug!(command = %format_env_command(env, args), "check started");
+    let started = Instant::now();
+    let output = run_command_with_timeout(&mut command, timeout).await;
+    let elapsed = started.elapsed();
+
+    let result = match output {
+        Ok(output) => {
+            let mut captured = CheckOutput::new(&output.stderr, &output.stdout);
+            if output.status.success() {
+                Ok(captured)
+            } else {
+                captured.invalid = is_unknown_feature_error(&captured.stderr);
+                Err(captured)
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+            Err(CheckOutput::error(err.to_string()))
+        }
+        Err(err) => Err(CheckOutput::error(spawn_error(configs, err).to_string())),
+    };
+    debug!(
+        command = %format_env_command(env, args),
+        passed = result.is_ok(),
+        elapsed_ms = elapsed.as_millis() as u64,
+        "check finished"
+    );
+    (result, elapsed)
+}
+
+/// Spawns `command` and waits for it to exit, only the streams set to
+/// [`Stdio::piped`] are captured in the returned output.
+async fn run_command(command: &mut Command) -> io::Result<Output> {
+    command.kill_on_drop(true).spawn()?.wait_with_output().await
+}
+
+/// Like [`run_command`], but kills the child and returns an
+/// [`io::ErrorKind::TimedOut`] error if it runs for longer than `timeout`.
+async fn run_command_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> io::Result<Output> {
+    let Some(timeout) = timeout else {
+        return run_command(command).await;
+    };
+
+    // Dropping the pending future drops the child, which kills it.
+    match tokio::time::timeout(timeout, run_command(command)).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("Timed out after {}s", timeout.as_secs()),
+        )),
+    }
+}
+
+/// Builds the cargo arguments of the warm-up step, if any. It is the only
+/// step allowed to fetch dependencies, the checks then run against the
+/// warmed cache even with `offline`.
+fn warmup_args(configs: &GlobalConfig) -> Option<Vec<String>> {
+    let mut args = toolchain_args(configs);
+    match configs.warmup {
+        Warmup::None => return None,
+        // Fetching doesn't take features, nor the arguments meant for the checks.
+        Warmup::Fetch => {
+            args.push("fetch".to_string());
+            if let Some(manifest) = &configs.manifest_path {
+                args.push("--manifest-path".to_string());
+                args.push(manifest.display().to_string());
+            }
+        }
+        Warmup::BuildAll => {
+            args.push("build".to_string());
+            args.extend(package_args(configs));
+            args.push("--all-features".to_string());
+            // Built with the profile of the checks, so they reuse the dependencies.
+            args.extend(profile_args(configs));
+        }
+    }
+    if let Some(target) = &configs.target {
+        args.push("--target".to_string());
+        args.push(target.clone());
+    }
+    if configs.locked {
+        args.push("--locked".to_string());
+    }
+    if configs.warmup == Warmup::BuildAll {
+        args.extend(configs.extra_cargo_args.iter().cloned());
+    }
+    Some(args)
+}
+
+/// Runs the warm-up step of [`warmup_args`], mirroring cargo's progress on
+/// `pb`. Does nothing when `warmup` is `none`.
+pub async fn run_cargo_build(
+    project_dir: &Path,
+    configs: &GlobalConfig,
+    pb: &ProgressBar,
+) -> Result<(), MultiCheckError> {
+    let Some(args) = warmup_args(configs) else {
+        return Ok(());
+    };
+    pb.set_message("Fetching dependencies");
+    let mut child = cargo_command(configs)
+        .args(args)
+        .current_dir(project_dir)
+        .stderr(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| spawn_error(configs, err))?;
+
+    // Cargo reports its progress ("Compiling foo v1.0.0") on stderr, mirror it
+    // on the spinner while keeping the whole output for error reporting.
+    let mut stderr = String::new();
+    if let Some(pipe) = child.stderr.take() {
+        let mut lines = BufReader::new(pipe).lines();
+        while let Some(line) = lines.next_line().await? {
+            let status = line.trim();
+            if !status.is_empty() {
+                pb.set_message(status.to_string());
+            }
+            stderr.push_str(&line);
+            stderr.push('\n');
+        }
+    }
+
+    if child.wait().await?.success() {
+        Ok(())
+    } else {
+        Err(MultiCheckError::Warmup(stderr))
+    }
+}
+
+/// Waits for a permit from `semaphore`, then spawns `task` into `tasks`. The
+/// permit is held until the task completes, so that no more than the
+/// semaphore's permits worth of tasks exist at once.
+async fn spawn_with_permit<F, T>(semaphore: &Arc<Semaphore>, tasks: &mut JoinSet<T>, task: F)
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let permit = semaphore
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("Semaphore closed");
+    tasks.spawn(async move {
+        let _permit = permit;
+        task.await
+    });
+}
+
+pub async fn clear_project(project: &RustProject) -> Result<(), String> {
+    let status = cargo_command(&project.configs)
+        .arg("clean")
+        .args(package_args(&project.configs))
+        .current_dir(&project.path)
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        // Waiting on the status alone would close the pipes, cargo then
+        // fails to print what it removed.
+        .output()
+        .await;
+
+    match status {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(_) => Err("Failed to clean project".to_string()),
+        Err(err) => Err(spawn_error(&project.configs, err).to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CheckCommand;
+    use crate::test_support::{global_config, strings};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn check_args_appends_extra_cargo_args_in_order() {
+        let mut configs = global_config();
+        configs.extra_cargo_args = strings(&["--locked", "--offline"]);
+
+        let args = check_args(&configs, CheckCommand::Check, &strings(&["a", "b"]));
+
+        assert_eq!(
+            args,
+            strings(&[
+                "check",
+                "--no-default-features",
+                "--features",
+                "a b",
+                "--locked",
+                "--offline"
+            ])
+        );
+        assert_eq!(
+            format_command(&args),
+            "cargo check --no-default-features --features \"a b\" --locked --offline"
+        );
+        assert_eq!(
+            check_args(&configs, CheckCommand::Check, &[]),
+            strings(&["check", "--locked", "--offline"])
+        );
+    }
+
+    #[test]
+    fn feature_cargo_args_follow_the_features_of_the_combination() {
+        let mut configs = global_config();
+        configs.extra_cargo_args = strings(&["--", "--nocapture"]);
+        configs.feature_cargo_args = BTreeMap::from([
+            ("simd".to_string(), strings(&["--config", "simd.toml"])),
+            ("alloc".to_string(), strings(&["--cfg-alloc"])),
+        ]);
+
+        assert_eq!(
+            check_args(&configs, CheckCommand::Test, &strings(&["simd", "alloc"])),
+            strings(&[
+                "test",
+                "--no-default-features",
+                "--features",
+                "simd alloc",
+                "--cfg-alloc",
+                "--config",
+                "simd.toml",
+                "--",
+                "--nocapture"
+            ])
+        );
+        assert_eq!(
+            check_args(&configs, CheckCommand::Test, &strings(&["other"]))[4..],
+            strings(&["--", "--nocapture"])
+        );
+        assert_eq!(
+            feature_args(&configs, &strings(&[ALL_FEATURES])),
+            strings(&["--cfg-alloc", "--config", "simd.toml"])
+        );
+    }
+
+    #[test]
+    fn check_args_handles_special_combinations() {
+        let configs = global_config();
+
+        assert_eq!(
+            check_args(&configs, CheckCommand::Check, &[]),
+            strings(&["check"])
+        );
+        assert_eq!(
+            check_args(&configs, CheckCommand::Check, &strings(&[ALL_FEATURES])),
+            strings(&["check", "--all-features"])
+        );
+        assert_eq!(
+            check_args(&configs, CheckCommand::Doc, &strings(&["a"])),
+            strings(&[
+                "doc",
+                "--no-deps",
+                "--no-default-features",
+                "--features",
+                "a"
+            ])
+        );
+    }
+
+    #[test]
+    fn profile_applies_to_checks_and_warmup_build() {
+        let mut configs = global_config();
+        configs.profile = Some("release".to_string());
+        configs.warmup = Warmup::BuildAll;
+
+        assert_eq!(
+            format_command(&check_args(
+                &configs,
+                CheckCommand::Clippy,
+                &strings(&["a"])
+            )),
+            "cargo clippy --no-default-features --features a --release"
+        );
+        assert_eq!(
+            warmup_args(&configs),
+            Some(strings(&["build", "--all-features", "--release"]))
+        );
+
+        configs.profile = Some("ci".to_string());
+        assert_eq!(
+            check_args(&configs, CheckCommand::Clippy, &[]),
+            strings(&["clippy", "--profile", "ci"])
+        );
+    }
+
+    #[test]
+    fn check_args_can_keep_default_features() {
+        let mut configs = global_config();
+        configs.keep_default_features = true;
+
+        assert_eq!(
+            format_command(&check_args(
+                &configs,
+                CheckCommand::Check,
+                &strings(&["a", "b"])
+            )),
+            "cargo check --features \"a b\""
+        );
+    }
+
+    #[test]
+    fn check_args_puts_toolchain_first() {
+        let mut configs = global_config();
+        configs.toolchain = Some("nightly".to_string());
+
+        assert_eq!(
+            format_command(&check_args(
+                &configs,
+                CheckCommand::Clippy,
+                &strings(&["a"])
+            )),
+            "cargo +nightly clippy --no-default-features --features a"
+        );
+    }
+
+    #[test]
+    fn supersets_of_failures_are_detected() {
+        let failures = vec![strings(&["a", "b"])];
+
+        assert!(is_superset_of_any(&strings(&["a", "c", "b"]), &failures));
+        assert!(is_superset_of_any(&strings(&["a", "b"]), &failures));
+        assert!(!is_superset_of_any(&strings(&["a", "c"]), &failures));
+        assert!(!is_superset_of_any(&strings(&["a"]), &[Vec::new()]));
+    }
+
+    #[test]
+    fn unknown_features_are_told_apart_from_build_failures() {
+        assert!(is_unknown_feature_error(
+            "error: the package 'demo' does not contain this feature: nope\n"
+        ));
+        assert!(is_unknown_feature_error(
+            "error: none of the selected packages contains these features: a, b\n"
+        ));
+        assert!(is_unknown_feature_error(
+            "error: Package `demo v0.1.0 (/demo)` does not have the feature `nope`\n"
+        ));
+        assert!(!is_unknown_feature_error(
+            "error[E0425]: cannot find value `x`\nerror: could not compile `demo`\n"
+        ));
+    }
+
+    #[test]
+    fn target_dir_goes_before_separator() {
+        let mut args = strings(&["clippy", "--locked", "--", "-D", "warnings"]);
+
+        insert_before_separator(&mut args, strings(&["--target-dir", "t"]));
+
+        assert_eq!(
+            args,
+            strings(&[
+                "clippy",
+                "--locked",
+                "--target-dir",
+                "t",
+                "--",
+                "-D",
+                "warnings"
+            ])
+        );
+    }
+
+    #[test]
+    fn check_args_selects_package() {
+        let mut configs = global_config();
+        configs.package = Some("member".to_string());
+
+        assert_eq!(
+            check_args(&configs, CheckCommand::Check, &strings(&["a"])),
+            strings(&[
+                "check",
+                "-p",
+                "member",
+                "--no-default-features",
+                "--features",
+                "a"
+            ])
+        );
+    }
+
+    #[test]
+    fn only_the_warmup_build_goes_online() {
+        let mut configs = global_config();
+        configs.offline = true;
+        configs.locked = true;
+        configs.extra_cargo_args = strings(&["--frozen"]);
+
+        assert_eq!(
+            check_args(&configs, CheckCommand::Check, &[]),
+            strings(&["check", "--locked", "--offline", "--frozen"])
+        );
+        assert_eq!(warmup_args(&configs), Some(strings(&["fetch", "--locked"])));
+        configs.warmup = Warmup::BuildAll;
+        assert_eq!(
+            warmup_args(&configs),
+            Some(strings(&[
+                "build",
+                "--all-features",
+                "--locked",
+                "--frozen"
+            ]))
+        );
+        configs.warmup = Warmup::None;
+        assert_eq!(warmup_args(&configs), None);
+    }
+
+    #[test]
+    fn check_args_passes_target() {
+        let mut configs = global_config();
+        configs.target = Some("thumbv7em-none-eabihf".to_string());
+
+        assert_eq!(
+            check_args(&configs, CheckCommand::Check, &strings(&["a"])),
+            strings(&[
+                "check",
+                "--no-default-features",
+                "--features",
+                "a",
+                "--target",
+                "thumbv7em-none-eabihf"
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn delta_debug_finds_minimal_failing_subset() {
+        let mut budget = 100;
+        let mut runs = 0;
+
+        let minimal = delta_debug(
+            strings(&["a", "b", "c", "d", "e", "f"]),
+            &mut budget,
+            |subset| {
+                runs += 1;
+                async move { subset.contains(&"b".to_string()) && subset.contains(&"e".to_string()) }
+            },
+        )
+        .await;
+
+        assert_eq!(minimal, strings(&["b", "e"]));
+        assert_eq!(budget, 100 - runs);
+    }
+
+    #[tokio::test]
+    async fn delta_debug_stops_when_budget_runs_out() {
+        let mut budget = 0;
+
+        let minimal = delta_debug(strings(&["a", "b"]), &mut budget, |_| async { true }).await;
+
+        assert_eq!(minimal, strings(&["a", "b"]));
+    }
+
+    #[test]
+    fn flags_are_appended_to_the_inherited_ones() {
+        let mut configs = global_config();
+        let parent = |name: &str| (name == "RUSTFLAGS").then(|| "--cfg foo".to_string());
+
+        assert!(cargo_env(&configs, &[], parent).is_empty());
+
+        configs.env = HashMap::from([
+            ("RUSTDOCFLAGS".to_string(), "--cfg docsrs".to_string()),
+            ("MY_VAR".to_string(), "1".to_string()),
+        ]);
+        let vars = cargo_env(&configs, &FLAGS_VARIABLES, parent);
+
+        assert_eq!(vars["RUSTFLAGS"], "--cfg foo -D warnings");
+        assert_eq!(vars["RUSTDOCFLAGS"], "--cfg docsrs -D warnings");
+        assert_eq!(vars["MY_VAR"], "1");
+        assert!(!cargo_env(&configs, &[], |_| None).contains_key("RUSTFLAGS"));
+
+        configs.env.clear();
+        let args = check_args(&configs, CheckCommand::Check, &[]);
+        assert_eq!(
+            format_env_command(&cargo_env(&configs, &FLAGS_VARIABLES, |_| None), &args),
+            "RUSTDOCFLAGS=\"-D warnings\" RUSTFLAGS=\"-D warnings\" cargo check"
+        );
+        let doc = check_env(&configs, CheckCommand::Doc);
+        assert!(doc["RUSTDOCFLAGS"].ends_with("-D warnings"));
+        assert!(!doc.contains_key("RUSTFLAGS"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_doc_checks_report_their_rustdocflags() {
+        let mut configs = global_config();
+        configs.cargo_bin = Some("false".to_string());
+        configs.command = Some(CheckCommand::Doc);
+        let combo = strings(&["a"]);
+
+        let runs = run_commands(
+            &configs,
+            Path::new("."),
+            &combo,
+            None,
+            &ProgressBar::hidden(),
+        )
+        .await;
+        let mut report = RunReport::default();
+        Outcome::Checked(combo, runs).record(&mut report);
+
+        let command = &report.failed[0].command;
+        assert!(command.starts_with("RUSTDOCFLAGS="), "{}", command);
+        assert!(
+            command.contains("-D warnings\" cargo doc --no-deps"),
+            "{}",
+            command
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failures_report_the_configured_env() {
+        let mut configs = global_config();
+        configs.cargo_bin = Some("false".to_string());
+        configs.env = HashMap::from([
+            ("MY_VAR".to_string(), "a b".to_string()),
+            ("RUSTFLAGS".to_string(), "--cfg foo".to_string()),
+        ]);
+
+        let runs = run_commands(&configs, Path::new("."), &[], None, &ProgressBar::hidden()).await;
+        let mut report = RunReport::default();
+        Outcome::Checked(Vec::new(), runs).record(&mut report);
+
+        let command = &report.failed[0].command;
+        assert!(
+            command.starts_with("MY_VAR=\"a b\" RUSTFLAGS="),
+            "{}",
+            command
+        );
+        assert!(command.contains("--cfg foo\" cargo check"), "{}", command);
+    }
+
+    #[tokio::test]
+    async fn missing_cargo_binary_is_reported() {
+        let mut configs = global_config();
+        configs.cargo_bin = Some("/nonexistent/cargo".to_string());
+
+        let (result, _) = make_checks(
+            &BTreeMap::new(),
+            &check_args(&configs, CheckCommand::Check, &[]),
+            Path::new("."),
+            &configs,
+            &ProgressBar::hidden(),
+        )
+        .await;
+
+        let output = result.unwrap_err();
+        assert_eq!(output.stderr, "cargo binary not found: /nonexistent/cargo");
+    }
+
+    #[test]
+    fn checks_share_the_cpus() {
+        let mut configs = global_config();
+        configs.cargo_jobs = Some(3);
+
+        let args = run_args(&configs, check_args(&configs, CheckCommand::Test, &[]));
+        assert_eq!(
+            format_command(&args),
+            "cargo test --message-format=json --jobs 3"
+        );
+        let args = run_args(&configs, strings(&["test", "-j2", "--", "-j"]));
+        assert_eq!(
+            format_command(&args),
+            "cargo test -j2 --message-format=json -- -j"
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_stops_at_the_first_success() {
+        let flaky = |failures: usize, retries: usize| async move {
+            let mut attempts = 0;
+            let (result, elapsed) = retry(retries, || {
+                attempts += 1;
+                let result = if attempts > failures { Ok(()) } else { Err(()) };
+                async move { (result, Duration::from_secs(1)) }
+            })
+            .await;
+            (result, elapsed, attempts)
+        };
+
+        assert_eq!(flaky(2, 2).await, (Ok(()), Duration::from_secs(3), 3));
+        assert_eq!(flaky(2, 1).await, (Err(()), Duration::from_secs(2), 2));
+        assert_eq!(flaky(0, 5).await, (Ok(()), Duration::from_secs(1), 1));
+    }
+
+    #[tokio::test]
+    async fn run_command_with_timeout_kills_slow_child() {
+        let mut command = Command::new("sleep");
+        command.arg("5").stdout(Stdio::piped());
+
+        let timer = Instant::now();
+        let err = run_command_with_timeout(&mut command, Some(Duration::from_millis(100)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn spawn_with_permit_never_exceeds_limit() {
+        let limit = 3;
+        let semaphore = Arc::new(Semaphore::new(limit));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = JoinSet::new();
+
+        for _ in 0..30 {
+            let running = running.clone();
+            let peak = peak.clone();
+            spawn_with_permit(&semaphore, &mut tasks, async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+            .await;
+            while let Some(result) = tasks.try_join_next() {
+                result.unwrap();
+            }
+        }
+        while let Some(result) = tasks.join_next().await {
+            result.unwrap();
+        }
+
+        assert!(peak.load(Ordering::SeqCst) <= limit);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,9 @@
+use crate::config::GlobalConfig;
+
+pub fn global_config() -> GlobalConfig {
+    toml::from_str("concurrency = 1\nclean = false\nclear_terminal = false").unwrap()
+}
+
+pub fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|s| s.to_string()).collect()
+}
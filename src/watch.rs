@@ -0,0 +1,101 @@
+use notify::{Event, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long the files must stay untouched before a change triggers a new run,
+/// so that saving several files at once only runs the checks once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Waits until `cargo_toml` or a file under the `src` directory next to it
+/// changes, returning false if Ctrl-C is pressed first.
+pub async fn wait_for_changes(cargo_toml: &Path) -> bool {
+    // Events carry absolute paths.
+    let cargo_toml = &cargo_toml
+        .canonicalize()
+        .unwrap_or_else(|_| cargo_toml.to_path_buf());
+    let project_dir = cargo_toml.parent().unwrap_or(Path::new("."));
+    let src = project_dir.join("src");
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            if !event.kind.is_access() {
+                let _ = tx.send(event.paths);
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            eprintln!("Failed to watch {:?}: {}", project_dir, err);
+            return false;
+        }
+    };
+    // Editors often replace files rather than writing them, watch the
+    // directory of the manifest rather than the file itself.
+    for (path, mode) in [
+        (project_dir, RecursiveMode::NonRecursive),
+        (src.as_path(), RecursiveMode::Recursive),
+    ] {
+        if let Err(err) = watcher.watch(path, mode) {
+            eprintln!("Failed to watch {:?}: {}", path, err);
+        }
+    }
+
+    let relevant = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .any(|path| path == cargo_toml || path.starts_with(&src))
+    };
+    loop {
+        tokio::select! {
+            paths = rx.recv() => match paths {
+                Some(paths) if relevant(&paths) => break,
+                Some(_) => {}
+                None => return false,
+            },
+            _ = tokio::signal::ctrl_c() => return false,
+        }
+    }
+    // Debounce: wait for the burst of events to settle.
+    loop {
+        tokio::select! {
+            paths = rx.recv() => if paths.is_none() {
+                return true;
+            },
+            _ = tokio::time::sleep(DEBOUNCE) => return true,
+            _ = tokio::signal::ctrl_c() => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn source_changes_end_the_wait() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "").unwrap();
+        let cargo_toml = dir.join("Cargo.toml");
+
+        let touch = {
+            let dir = dir.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                fs::write(dir.join("Cargo.lock"), "").unwrap();
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                fs::write(dir.join("src/lib.rs"), "").unwrap();
+            })
+        };
+
+        let changed = tokio::time::timeout(Duration::from_secs(10), wait_for_changes(&cargo_toml))
+            .await
+            .expect("change was not noticed");
+        touch.await.unwrap();
+        assert!(changed);
+    }
+}